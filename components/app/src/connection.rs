@@ -1,4 +1,12 @@
-use futures::{FutureExt, TryFutureExt, StreamExt, SinkExt};
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::{FutureExt, TryFutureExt, Stream, StreamExt, SinkExt};
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
 
@@ -13,33 +21,198 @@ use common::multi_consumer::{multi_consumer_service, MultiConsumerClient};
 
 use crate::connect::NodeConnectionTuple;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NodeConnectionError {
     SpawnError,
+    /// The app server sent `AppServerToApp::Report` outside of the initial handshake.
+    UnexpectedReport,
+    /// The app server sent `AppServerToApp::ReportMutations` to an app that wasn't granted the
+    /// `reports` permission.
+    UnexpectedReportMutations,
+    /// The underlying connection to the app server closed.
+    ConnectionClosed,
+}
+
+impl fmt::Display for NodeConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeConnectionError::SpawnError => write!(f, "failed to spawn a NodeConnection task"),
+            NodeConnectionError::UnexpectedReport => write!(f, "received unexpected AppServerToApp::Report message"),
+            NodeConnectionError::UnexpectedReportMutations => write!(f, "received unexpected AppServerToApp::ReportMutations message"),
+            NodeConnectionError::ConnectionClosed => write!(f, "connection to the app server closed"),
+        }
+    }
+}
+
+impl StdError for NodeConnectionError {}
+
+/// A cloneable, type-erased error, so the single terminal error produced when a
+/// [`NodeConnection`]'s dispatch loop exits can be handed out to every interested consumer
+/// (the `routes_mc`/`send_funds_mc` fan-out subscribers, and the report `StateClient`) instead of
+/// being observable by only whichever one first caught it.
+#[derive(Clone)]
+pub struct SharedError(Arc<dyn StdError + Send + Sync>);
+
+impl SharedError {
+    pub fn new<E: StdError + Send + Sync + 'static>(err: E) -> Self {
+        SharedError(Arc::new(err))
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl Deref for SharedError {
+    type Target = dyn StdError + Send + Sync;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+/// How a [`BoundedSender`] behaves once its underlying channel has no room left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferPolicy {
+    /// Apply natural backpressure: `send()` awaits until there is room.
+    Block,
+    /// When the channel is full, discard the new item and report success (`Ok(())`) instead of
+    /// blocking. Items already queued are kept: `mpsc::Sender` cannot remove them, so no queued
+    /// item is ever evicted to make room.
+    DropOldest,
+    /// Reject the new item immediately with `BufferOverflowError`.
+    RejectNew,
+}
+
+#[derive(Debug)]
+pub struct BufferOverflowError;
+
+/// Point-in-time view of a [`BoundedSender`]'s queue, for metrics/introspection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferMetrics {
+    /// Number of `send()` calls currently waiting for room under [`BufferPolicy::Block`]. Items
+    /// already sitting in the channel are not counted, and under the other policies this stays 0.
+    pub depth: usize,
+    pub total_dropped: usize,
+}
+
+struct BufferCounters {
+    depth: AtomicUsize,
+    total_dropped: AtomicUsize,
+}
+
+/// A bounded queue in front of an `mpsc::Sender`, modeled on a service buffer: once the channel
+/// is full, overflow is handled according to a [`BufferPolicy`] instead of growing without limit,
+/// protecting the sending side from unbounded memory growth when the receiver falls behind.
+/// [`BoundedSender::metrics`] exposes the number of blocked senders plus a running drop count.
+#[derive(Clone)]
+pub struct BoundedSender<T> {
+    sender: mpsc::Sender<T>,
+    policy: BufferPolicy,
+    counters: Arc<BufferCounters>,
+}
+
+impl<T> BoundedSender<T> {
+    pub fn new(sender: mpsc::Sender<T>, policy: BufferPolicy) -> Self {
+        BoundedSender {
+            sender,
+            policy,
+            counters: Arc::new(BufferCounters {
+                depth: AtomicUsize::new(0),
+                total_dropped: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn metrics(&self) -> BufferMetrics {
+        BufferMetrics {
+            depth: self.counters.depth.load(Ordering::Relaxed),
+            total_dropped: self.counters.total_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn send(&mut self, item: T) -> Result<(), BufferOverflowError> {
+        match self.policy {
+            BufferPolicy::Block => {
+                self.counters.depth.fetch_add(1, Ordering::Relaxed);
+                let res = await!(self.sender.send(item));
+                self.counters.depth.fetch_sub(1, Ordering::Relaxed);
+                res.map_err(|_| BufferOverflowError)
+            },
+            BufferPolicy::DropOldest | BufferPolicy::RejectNew => {
+                match self.sender.try_send(item) {
+                    Ok(()) => Ok(()),
+                    Err(_) => {
+                        self.counters.total_dropped.fetch_add(1, Ordering::Relaxed);
+                        match self.policy {
+                            BufferPolicy::RejectNew => Err(BufferOverflowError),
+                            // `mpsc::Sender` has no way to reach into the channel and pop an
+                            // already-queued item, so "drop the oldest" degrades to dropping the
+                            // item we were just asked to send - there is no backlog to reach into.
+                            _ => Ok(()),
+                        }
+                    },
+                }
+            },
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct NodeConnection {
-    sender: mpsc::Sender<AppToAppServer>,
+    sender: BoundedSender<AppToAppServer>,
     app_permissions: AppPermissions,
     report_client: StateClient<BatchMutable<NodeReport>,Vec<NodeReportMutation>>,
     routes_mc: MultiConsumerClient<ClientResponseRoutes>,
     send_funds_mc: MultiConsumerClient<ResponseReceived>,
+    terminal_error: Arc<Mutex<Option<SharedError>>>,
 }
 
 // TODO:
 pub struct AppReport;
 pub struct AppConfig;
-pub struct AppRoutes;
-pub struct AppSendFunds;
+
+/// A subscription to this connection's stream of routes responses, handed out by
+/// [`NodeConnection::request_routes`]. Wraps the receiving half of the `routes_mc` fan-out so a
+/// caller (e.g. [`crate::pool::NodePool::routes`]) can pull responses without reaching into
+/// `NodeConnection` internals.
+pub struct AppRoutes(mpsc::Receiver<ClientResponseRoutes>);
+
+/// A subscription to this connection's stream of send-funds responses. See [`AppRoutes`].
+pub struct AppSendFunds(mpsc::Receiver<ResponseReceived>);
+
+impl Stream for AppRoutes {
+    type Item = ClientResponseRoutes;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl Stream for AppSendFunds {
+    type Item = ResponseReceived;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
 
 impl NodeConnection {
-    pub fn new<S>(conn_tuple: NodeConnectionTuple, spawner: &mut S) 
-        -> Result<Self, NodeConnectionError> 
+    pub fn new<S>(conn_tuple: NodeConnectionTuple, buffer_policy: BufferPolicy, spawner: &mut S)
+        -> Result<Self, NodeConnectionError>
     where
         S: Spawn,
     {
         let (app_permissions, opt_node_report, (sender, mut receiver)) = conn_tuple;
+        let sender = BoundedSender::new(sender, buffer_policy);
 
         // Spawn report service:
         assert_eq!(app_permissions.reports, opt_node_report.is_some());
@@ -55,28 +228,35 @@ impl NodeConnection {
                 .map_err(|e| error!("state_service() error: {:?}", e))
                 .map(|_| ());
             spawner.spawn(state_service_fut)
-                .map_err(|_| NodeConnectionError::SpawnError);
+                .map_err(|_| NodeConnectionError::SpawnError)?;
         }
 
-        let (mut incoming_routes_sender, incoming_routes) = mpsc::channel(0);
+        let (incoming_routes_sender, incoming_routes) = mpsc::channel(0);
+        let mut incoming_routes_sender = BoundedSender::new(incoming_routes_sender, buffer_policy);
         let (requests_sender, incoming_requests) = mpsc::channel(0);
         let routes_mc = MultiConsumerClient::new(requests_sender);
         let routes_fut = multi_consumer_service(incoming_routes, incoming_requests)
             .map_err(|e| error!("Routes multi_consumer_service() error: {:?}", e))
             .map(|_| ());
         spawner.spawn(routes_fut)
-                .map_err(|_| NodeConnectionError::SpawnError);
+                .map_err(|_| NodeConnectionError::SpawnError)?;
 
-        let (mut incoming_send_funds_sender, incoming_send_funds) = mpsc::channel(0);
+        let (incoming_send_funds_sender, incoming_send_funds) = mpsc::channel(0);
+        let mut incoming_send_funds_sender = BoundedSender::new(incoming_send_funds_sender, buffer_policy);
         let (requests_sender, incoming_requests) = mpsc::channel(0);
         let send_funds_mc = MultiConsumerClient::new(requests_sender);
         let send_funds_fut = multi_consumer_service(incoming_send_funds, incoming_requests)
             .map_err(|e| error!("Routes multi_consumer_service() error: {:?}", e))
             .map(|_| ());
         spawner.spawn(send_funds_fut)
-                .map_err(|_| NodeConnectionError::SpawnError);
-        
-        async move {
+                .map_err(|_| NodeConnectionError::SpawnError)?;
+
+        let terminal_error: Arc<Mutex<Option<SharedError>>> = Arc::new(Mutex::new(None));
+        let dispatch_terminal_error = terminal_error.clone();
+        let dispatch_fut = async move {
+            let set_terminal_error = move |err: NodeConnectionError| {
+                *dispatch_terminal_error.lock().unwrap() = Some(SharedError::new(err));
+            };
             while let Some(message) = await!(receiver.next()) {
                 match message {
                     AppServerToApp::ResponseReceived(response_received) => {
@@ -86,11 +266,13 @@ impl NodeConnection {
                         // TODO: Maybe somehow redesign the type AppServerToApp
                         // so that we don't have this edge case?
                         error!("Received unexpected AppServerToApp::Report message. Aborting.");
+                        set_terminal_error(NodeConnectionError::UnexpectedReport);
                         return;
                     },
                     AppServerToApp::ReportMutations(node_report_mutations) => {
                         if !is_reports {
                             error!("Received unexpected AppServerToApp::ReportMutations message. Aborting.");
+                            set_terminal_error(NodeConnectionError::UnexpectedReportMutations);
                             return;
                         }
                         let _ = await!(incoming_mutations_sender.send(node_report_mutations));
@@ -100,7 +282,13 @@ impl NodeConnection {
                     },
                 }
             }
+            // `receiver` was closed without an explicit terminal error above - still worth
+            // surfacing to `routes_mc`/`send_funds_mc`/`report_client` consumers, who would
+            // otherwise just see their own streams end with no explanation.
+            set_terminal_error(NodeConnectionError::ConnectionClosed);
         };
+        spawner.spawn(dispatch_fut)
+                .map_err(|_| NodeConnectionError::SpawnError)?;
 
         Ok(NodeConnection {
             sender,
@@ -108,9 +296,23 @@ impl NodeConnection {
             report_client,
             routes_mc,
             send_funds_mc,
+            terminal_error,
         })
     }
 
+    /// Current depth/drop count of the buffer in front of the app-server sender, used by
+    /// [`crate::pool::NodePool`] to tell a backed-up connection apart from a healthy one.
+    pub fn buffer_metrics(&self) -> BufferMetrics {
+        self.sender.metrics()
+    }
+
+    /// The error the dispatch loop set before it exited, if it has exited. All of
+    /// `routes_mc`/`send_funds_mc`/`report_client`'s consumers see their streams end for this
+    /// same reason, so this is the one place to ask "why".
+    pub fn terminal_error(&self) -> Option<SharedError> {
+        self.terminal_error.lock().unwrap().clone()
+    }
+
     pub fn report() -> Option<AppReport> {
         unimplemented!();
     }
@@ -125,11 +327,15 @@ impl NodeConnection {
         unimplemented!();
     }
 
-    pub fn routes() -> Option<AppRoutes> {
-        unimplemented!();
+    /// Subscribe this connection's `routes_mc` fan-out, handing back a fresh stream of routes
+    /// responses. `None` if the dispatch loop backing `routes_mc` has already exited - callers
+    /// should fall back on [`NodeConnection::terminal_error`] to find out why.
+    pub async fn request_routes(&mut self) -> Option<AppRoutes> {
+        Some(AppRoutes(await!(self.routes_mc.request_stream())?))
     }
 
-    pub fn send_funds() -> Option<AppSendFunds> {
-        unimplemented!();
+    /// Subscribe this connection's `send_funds_mc` fan-out. See [`NodeConnection::request_routes`].
+    pub async fn request_send_funds(&mut self) -> Option<AppSendFunds> {
+        Some(AppSendFunds(await!(self.send_funds_mc.request_stream())?))
     }
 }
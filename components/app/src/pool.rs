@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::task::Spawn;
+use futures::Stream;
+
+use crate::connect::NodeConnectionTuple;
+use crate::connection::{AppRoutes, AppSendFunds, BufferPolicy, NodeConnection, NodeConnectionError};
+
+/// Wraps a [`NodePool`] subscription so that dropping it - ending that logical subscription -
+/// decrements the owning [`PoolEntry`]'s `outstanding` count to match the `fetch_add` done when
+/// it was handed out. Without this, `outstanding` only ever grows, and `pick_ready`'s
+/// "fewest outstanding requests" selection degenerates into "fewest times ever routed here"
+/// instead of reflecting how many subscriptions are actually still live.
+pub struct PooledStream<S> {
+    inner: S,
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl<S: Stream + Unpin> Stream for PooledStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+impl<S> Drop for PooledStream<S> {
+    fn drop(&mut self) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A [`NodePool::routes`] subscription. See [`PooledStream`].
+pub type PooledRoutes = PooledStream<AppRoutes>;
+/// A [`NodePool::send_funds`] subscription. See [`PooledStream`].
+pub type PooledSendFunds = PooledStream<AppSendFunds>;
+
+/// A connection is only routed to while its buffer is this shallow; once ticks back up past this
+/// depth it is treated as unhealthy rather than piling even more requests onto it.
+const READY_BUFFER_DEPTH: usize = 0;
+
+/// Opaque handle identifying one of a [`NodePool`]'s underlying connections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug)]
+pub enum NodePoolError {
+    /// No connection in the pool is currently ready to take a request.
+    NoReadyConnection,
+    /// `node_id` does not (or no longer) refer to a connection in this pool.
+    UnknownNode,
+    Connection(NodeConnectionError),
+}
+
+impl From<NodeConnectionError> for NodePoolError {
+    fn from(e: NodeConnectionError) -> Self {
+        NodePoolError::Connection(e)
+    }
+}
+
+struct PoolEntry {
+    connection: NodeConnection,
+    // Number of requests currently believed to be in flight against this connection.
+    outstanding: Arc<AtomicUsize>,
+    // Set to `false` by `NodePool::evict`. A connection whose dispatch loop exited is kept out of
+    // routing by its terminal error instead (see `is_ready`), not by this flag.
+    healthy: bool,
+    // Lazily reconnects this node, producing a fresh `NodeConnectionTuple` to feed back into
+    // `NodeConnection::new`. Returns `None` if the node isn't reachable right now.
+    reconnect: Box<dyn FnMut() -> Option<NodeConnectionTuple> + Send>,
+}
+
+/// Manages several [`NodeConnection`]s to redundant nodes, routing outgoing requests to whichever
+/// ready connection currently has the fewest outstanding requests, and transparently failing over
+/// when a connection errors or its dispatch loop exits. Readiness requires the connection to be
+/// marked healthy and its buffer not to be backed up.
+pub struct NodePool<S> {
+    next_id: usize,
+    entries: HashMap<NodeId, PoolEntry>,
+    buffer_policy: BufferPolicy,
+    spawner: S,
+}
+
+impl<S: Spawn> NodePool<S> {
+    pub fn new(buffer_policy: BufferPolicy, spawner: S) -> Self {
+        NodePool {
+            next_id: 0,
+            entries: HashMap::new(),
+            buffer_policy,
+            spawner,
+        }
+    }
+
+    /// Establish a connection from `conn_tuple` and add it to the pool. `reconnect` is kept
+    /// around so the node can later be lazily re-established via [`NodePool::reestablish`]
+    /// without the caller having to remember how each node was originally dialed.
+    pub fn insert(
+        &mut self,
+        conn_tuple: NodeConnectionTuple,
+        reconnect: impl FnMut() -> Option<NodeConnectionTuple> + Send + 'static,
+    ) -> Result<NodeId, NodePoolError> {
+        let connection = NodeConnection::new(conn_tuple, self.buffer_policy, &mut self.spawner)?;
+        let node_id = NodeId(self.next_id);
+        self.next_id += 1;
+        self.entries.insert(node_id, PoolEntry {
+            connection,
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            healthy: true,
+            reconnect: Box::new(reconnect),
+        });
+        Ok(node_id)
+    }
+
+    /// Mark a connection unhealthy so it is no longer routed to, e.g. because it errored or its
+    /// dispatch loop exited. Its entry stays in the pool, so the node can be brought back with
+    /// [`NodePool::reestablish`].
+    pub fn evict(&mut self, node_id: NodeId) {
+        if let Some(entry) = self.entries.get_mut(&node_id) {
+            entry.healthy = false;
+        }
+    }
+
+    /// Attempt to lazily re-establish a connection previously marked unhealthy, using the
+    /// reconnect factory supplied at [`NodePool::insert`] time.
+    pub fn reestablish(&mut self, node_id: NodeId) -> Result<(), NodePoolError> {
+        let conn_tuple = {
+            let entry = self.entries.get_mut(&node_id).ok_or(NodePoolError::UnknownNode)?;
+            (entry.reconnect)().ok_or(NodePoolError::NoReadyConnection)?
+        };
+        let connection = NodeConnection::new(conn_tuple, self.buffer_policy, &mut self.spawner)?;
+        let entry = self.entries.get_mut(&node_id).ok_or(NodePoolError::UnknownNode)?;
+        entry.connection = connection;
+        entry.healthy = true;
+        // A fresh counter: streams handed out by the old connection keep (and decrement) the old one.
+        entry.outstanding = Arc::new(AtomicUsize::new(0));
+        Ok(())
+    }
+
+    /// A connection is ready if it hasn't been marked unhealthy, its dispatch loop hasn't exited
+    /// with a terminal error, and its buffer isn't backed up.
+    fn is_ready(entry: &PoolEntry) -> bool {
+        entry.healthy
+            && entry.connection.terminal_error().is_none()
+            && entry.connection.buffer_metrics().depth <= READY_BUFFER_DEPTH
+    }
+
+    /// Pick the ready connection with the fewest outstanding requests.
+    fn pick_ready(&self) -> Option<NodeId> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| Self::is_ready(entry))
+            .min_by_key(|(_, entry)| entry.outstanding.load(Ordering::Relaxed))
+            .map(|(node_id, _)| *node_id)
+    }
+
+    /// Route a routes request to a ready connection, subscribing that connection's own
+    /// `routes_mc` fan-out and handing back the resulting stream as a single logical `AppRoutes`.
+    /// `outstanding` is decremented when the returned `PooledRoutes` is dropped.
+    pub async fn routes(&mut self) -> Result<PooledRoutes, NodePoolError> {
+        let node_id = self.pick_ready().ok_or(NodePoolError::NoReadyConnection)?;
+        let entry = self.entries.get_mut(&node_id).ok_or(NodePoolError::UnknownNode)?;
+        entry.outstanding.fetch_add(1, Ordering::Relaxed);
+        let outstanding = entry.outstanding.clone();
+        match await!(entry.connection.request_routes()) {
+            Some(inner) => Ok(PooledStream { inner, outstanding }),
+            None => {
+                outstanding.fetch_sub(1, Ordering::Relaxed);
+                Err(NodePoolError::NoReadyConnection)
+            },
+        }
+    }
+
+    /// Route a send-funds request to a ready connection. See [`NodePool::routes`].
+    pub async fn send_funds(&mut self) -> Result<PooledSendFunds, NodePoolError> {
+        let node_id = self.pick_ready().ok_or(NodePoolError::NoReadyConnection)?;
+        let entry = self.entries.get_mut(&node_id).ok_or(NodePoolError::UnknownNode)?;
+        entry.outstanding.fetch_add(1, Ordering::Relaxed);
+        let outstanding = entry.outstanding.clone();
+        match await!(entry.connection.request_send_funds()) {
+            Some(inner) => Ok(PooledStream { inner, outstanding }),
+            None => {
+                outstanding.fetch_sub(1, Ordering::Relaxed);
+                Err(NodePoolError::NoReadyConnection)
+            },
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use rusqlite::{params, Connection};
+
+/// A single net-value entry in the node's transaction history: one `events` row together with
+/// its per-currency balance delta, already carrying the correct sign. A payment debits the
+/// paying node (`-(amount + out_fees)`); an invoice credits the receiving node
+/// (`amount - in_fees`); a friend inconsistency event reports the balance jump directly, with no
+/// fees of its own to fold in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub counter: Vec<u8>,
+    pub time: i64,
+    pub currency: String,
+    /// Signed net change to our balance caused by this event, as a big-endian two's-complement
+    /// encoded `i128`.
+    pub net_value: Vec<u8>,
+}
+
+fn net_value_of_row(
+    event_type: &str,
+    amount: i128,
+    in_fees: i128,
+    out_fees: i128,
+) -> i128 {
+    match event_type {
+        "P" => -(amount + out_fees),
+        "I" => amount - in_fees,
+        // Friend inconsistency: `amount` already holds the signed balance delta
+        // (new_balance - old_balance), so fees don't apply.
+        "F" => amount,
+        _ => unreachable!("event_type check constraint guarantees one of P, I, F"),
+    }
+}
+
+/// Create the `transaction_history` view joining `events` and `event_balances`, computing a
+/// signed net value per `(counter, currency)` row. Run once as part of schema setup; a plain
+/// `CREATE VIEW` can't be expressed as a migration function argument so this is exposed as a
+/// statement to fold into a migration step.
+pub const CREATE_TRANSACTION_HISTORY_VIEW: &str = "
+    CREATE VIEW transaction_history AS
+    SELECT
+        events.counter       AS counter,
+        events.time          AS time,
+        event_balances.currency    AS currency,
+        event_balances.amount      AS amount,
+        event_balances.in_fees     AS in_fees,
+        event_balances.out_fees    AS out_fees,
+        event_balances.event_type  AS event_type
+    FROM events
+    JOIN event_balances ON event_balances.counter = events.counter;
+";
+
+fn decode_i128(bytes: &[u8]) -> i128 {
+    let mut buff = [0u8; 16];
+    buff.copy_from_slice(bytes);
+    i128::from_be_bytes(buff)
+}
+
+/// Read a friend-independent net-value transaction history, ordered oldest-first by `counter`
+/// (the total order column already used by `events`).
+pub fn transaction_history(conn: &Connection) -> rusqlite::Result<Vec<HistoryEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT counter, time, currency, amount, in_fees, out_fees, event_type
+         FROM transaction_history
+         ORDER BY counter ASC;",
+    )?;
+
+    let rows = stmt.query_map(params![], |row| {
+        let counter: Vec<u8> = row.get(0)?;
+        let time: i64 = row.get(1)?;
+        let currency: String = row.get(2)?;
+        let amount = decode_i128(&row.get::<_, Vec<u8>>(3)?);
+        let in_fees = decode_i128(&row.get::<_, Vec<u8>>(4)?);
+        let out_fees = decode_i128(&row.get::<_, Vec<u8>>(5)?);
+        let event_type: String = row.get(6)?;
+
+        let net_value = net_value_of_row(&event_type, amount, in_fees, out_fees);
+
+        Ok(HistoryEntry {
+            counter,
+            time,
+            currency,
+            net_value: net_value.to_be_bytes().to_vec(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    fn insert_event(conn: &Connection, counter: i64, time: i64) {
+        conn.execute(
+            "INSERT INTO events (counter, time, event_type, app_public_key, app_name)
+             VALUES (?1, ?2, 'P', ?3, 'app');",
+            params![counter.to_be_bytes().to_vec(), time, vec![0u8; 32]],
+        )
+        .unwrap();
+    }
+
+    fn insert_payment_balance(conn: &Connection, counter: i64, amount: i128, out_fees: i128) {
+        conn.execute(
+            "INSERT INTO event_balances (counter, currency, amount, in_fees, out_fees, event_type)
+             VALUES (?1, 'FST', ?2, ?3, ?4, 'P');",
+            params![
+                counter.to_be_bytes().to_vec(),
+                amount.to_be_bytes().to_vec(),
+                0i128.to_be_bytes().to_vec(),
+                out_fees.to_be_bytes().to_vec()
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_transaction_history_debits_payment_amount_and_fees() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // `create_database` already created the view (migration step 4).
+        create_database(&mut conn).unwrap();
+
+        insert_event(&conn, 1, 1000);
+        insert_payment_balance(&conn, 1, 100, 5);
+
+        let history = transaction_history(&conn).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(decode_i128(&history[0].net_value), -105);
+    }
+}
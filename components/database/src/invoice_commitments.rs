@@ -0,0 +1,189 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// A commitment (`src_hashed_lock`) for an invoice action that arrived before the corresponding
+/// `invoice_actions` row was created — possible because the commitment and the action that
+/// describes it can race in over the network. Cached here, keyed by `(invoice_id, action_id)`,
+/// until [`create_action`] claims it.
+pub const CREATE_PENDING_COMMITMENTS_TABLE: &str = "
+    CREATE TABLE pending_commitments(
+        invoice_id         BLOB NOT NULL,
+        action_id          BLOB NOT NULL,
+        src_hashed_lock    BLOB NOT NULL,
+        PRIMARY KEY(invoice_id, action_id),
+        FOREIGN KEY(invoice_id)
+           REFERENCES open_invoices(invoice_id)
+           ON DELETE CASCADE
+    );
+";
+
+/// Record a commitment that arrived before its `invoice_actions` row exists. Overwrites any
+/// earlier commitment cached for the same `(invoice_id, action_id)`, since only the most
+/// recently received one can still be valid.
+pub fn cache_commitment(
+    conn: &Connection,
+    invoice_id: &[u8],
+    action_id: &[u8],
+    src_hashed_lock: &[u8],
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO pending_commitments (invoice_id, action_id, src_hashed_lock)
+         VALUES (?1, ?2, ?3)
+         ON CONFLICT(invoice_id, action_id) DO UPDATE SET src_hashed_lock = excluded.src_hashed_lock;",
+        params![invoice_id, action_id, src_hashed_lock],
+    )?;
+    Ok(())
+}
+
+/// Create `invoice_actions` row for `(invoice_id, action_id)`, atomically claiming any
+/// commitment that was cached for it ahead of time and folding it into
+/// `opt_src_hashed_lock`, then clearing the cache entry. Must be called within the same
+/// transaction that would otherwise insert the bare `invoice_actions` row directly.
+pub fn create_action(
+    tx: &rusqlite::Transaction,
+    invoice_id: &[u8],
+    action_id: &[u8],
+    description: &str,
+    invoice_hash: &[u8],
+) -> rusqlite::Result<()> {
+    let opt_src_hashed_lock: Option<Vec<u8>> = tx
+        .query_row(
+            "SELECT src_hashed_lock FROM pending_commitments WHERE invoice_id = ?1 AND action_id = ?2;",
+            params![invoice_id, action_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    tx.execute(
+        "INSERT INTO invoice_actions (invoice_id, action_id, description, invoice_hash, opt_src_hashed_lock)
+         VALUES (?1, ?2, ?3, ?4, ?5);",
+        params![invoice_id, action_id, description, invoice_hash, opt_src_hashed_lock],
+    )?;
+
+    tx.execute(
+        "DELETE FROM pending_commitments WHERE invoice_id = ?1 AND action_id = ?2;",
+        params![invoice_id, action_id],
+    )?;
+
+    Ok(())
+}
+
+/// Reap every `open_invoices` row whose `expire_at` is at or before `now`, recording a
+/// terminating `friend_inconsistency`-style event is not appropriate here (no friend is
+/// involved), so instead a plain `I`-typed `events`/`invoice_events` row is written per expired
+/// invoice before it (and its `ON DELETE CASCADE`d commitments/actions) are removed — giving the
+/// history view a record of *why* the invoice disappeared rather than it silently vanishing.
+pub fn reap_expired_invoices(
+    tx: &rusqlite::Transaction,
+    now: i64,
+    next_counter: &mut dyn FnMut() -> Vec<u8>,
+) -> rusqlite::Result<()> {
+    let expired: Vec<(Vec<u8>, String, Option<Vec<u8>>, Vec<u8>)> = {
+        let mut stmt = tx.prepare(
+            "SELECT invoice_id, currency, opt_amount, data_hash FROM open_invoices WHERE expire_at <= ?1;",
+        )?;
+        let rows = stmt.query_map(params![now], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    for (invoice_id, currency, opt_amount, data_hash) in expired {
+        let counter = next_counter();
+        tx.execute(
+            "INSERT INTO events (counter, time, event_type, app_public_key, app_name)
+             VALUES (?1, ?2, 'I', ?3, 'node');",
+            params![counter, now, vec![0u8; 32]],
+        )?;
+        tx.execute(
+            "INSERT INTO invoice_events (counter, serial_num, currency, amount, description, data_hash)
+             VALUES (?1, ?2, ?3, ?4, 'invoice expired', ?5);",
+            params![
+                counter,
+                invoice_id,
+                currency,
+                opt_amount.unwrap_or_else(|| 0u128.to_be_bytes().to_vec()),
+                data_hash
+            ],
+        )?;
+        tx.execute("DELETE FROM open_invoices WHERE invoice_id = ?1;", params![invoice_id])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    fn dummy_invoice(conn: &Connection, invoice_id: &[u8], expire_at: i64) {
+        conn.execute(
+            "INSERT INTO open_invoices (invoice_id, psk, description, data_hash, currency, opt_amount, expire_at)
+             VALUES (?1, ?1, 'desc', ?1, 'FST', NULL, ?2);",
+            params![invoice_id, expire_at],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cached_commitment_is_claimed_by_create_action() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // `create_database` already created `pending_commitments` (migration step 6).
+        create_database(&mut conn).unwrap();
+
+        let invoice_id = vec![0u8; 16];
+        dummy_invoice(&conn, &invoice_id, 9999999999);
+        let action_id = vec![1u8; 16];
+        let src_hashed_lock = vec![2u8; 32];
+
+        cache_commitment(&conn, &invoice_id, &action_id, &src_hashed_lock).unwrap();
+
+        let tx = conn.transaction().unwrap();
+        create_action(&tx, &invoice_id, &action_id, "desc", &[3u8; 32]).unwrap();
+        tx.commit().unwrap();
+
+        let claimed: Option<Vec<u8>> = conn
+            .query_row(
+                "SELECT opt_src_hashed_lock FROM invoice_actions WHERE invoice_id = ?1 AND action_id = ?2;",
+                params![invoice_id, action_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(claimed, Some(src_hashed_lock));
+
+        let remaining: i64 = conn
+            .query_row("SELECT COUNT(*) FROM pending_commitments;", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_reap_expired_invoices_records_event() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+
+        let invoice_id = vec![0u8; 16];
+        dummy_invoice(&conn, &invoice_id, 100);
+
+        let mut counter = 0i64;
+        {
+            let tx = conn.transaction().unwrap();
+            reap_expired_invoices(&tx, 200, &mut || {
+                counter += 1;
+                counter.to_be_bytes().to_vec()
+            })
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let invoices_left: i64 = conn
+            .query_row("SELECT COUNT(*) FROM open_invoices;", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(invoices_left, 0);
+
+        let events_recorded: i64 = conn
+            .query_row("SELECT COUNT(*) FROM invoice_events;", params![], |row| row.get(0))
+            .unwrap();
+        assert_eq!(events_recorded, 1);
+    }
+}
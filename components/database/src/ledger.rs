@@ -0,0 +1,206 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Why a ledger entry was posted, mirroring the `tag` check constraint on `ledger_entries`.
+/// Distinct from `events.event_type`: a single payment or invoice event can post more than one
+/// tagged entry (e.g. a payment posts both a `Payment` entry and an `OutFee` entry).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LedgerTag {
+    Payment,
+    Invoice,
+    InFee,
+    OutFee,
+    Inconsistency,
+}
+
+impl LedgerTag {
+    fn as_str(self) -> &'static str {
+        match self {
+            LedgerTag::Payment => "payment",
+            LedgerTag::Invoice => "invoice",
+            LedgerTag::InFee => "in_fee",
+            LedgerTag::OutFee => "out_fee",
+            LedgerTag::Inconsistency => "inconsistency",
+        }
+    }
+}
+
+pub const CREATE_LEDGER_ENTRIES_TABLE: &str = "
+    CREATE TABLE ledger_entries(
+        entry_id      INTEGER PRIMARY KEY AUTOINCREMENT,
+        counter       BLOB NOT NULL,
+        account       TEXT NOT NULL,
+        currency      TEXT NOT NULL,
+        credit        BLOB NOT NULL,
+        debit         BLOB NOT NULL,
+        tag           TEXT CHECK (tag IN ('payment', 'invoice', 'in_fee', 'out_fee', 'inconsistency'))
+                      NOT NULL,
+        time          INTEGER NOT NULL,
+        FOREIGN KEY(counter)
+           REFERENCES events(counter)
+           ON DELETE CASCADE
+    );
+    CREATE INDEX idx_ledger_entries_account_currency ON ledger_entries(account, currency, entry_id);
+    CREATE INDEX idx_ledger_entries_counter ON ledger_entries(counter);
+";
+
+/// Post one double-entry ledger line. `credit` and `debit` are big-endian `u128` byte strings;
+/// exactly one of the two is expected to be non-zero for any single entry, matching the
+/// credit/debit convention of the rest of the double-entry ledger (a transfer between two
+/// accounts is two calls: a debit on one account and a matching credit on the other).
+pub fn post_entry(
+    conn: &Connection,
+    counter: &[u8],
+    account: &str,
+    currency: &str,
+    credit: u128,
+    debit: u128,
+    tag: LedgerTag,
+    time: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO ledger_entries (counter, account, currency, credit, debit, tag, time)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+        params![
+            counter,
+            account,
+            currency,
+            credit.to_be_bytes().to_vec(),
+            debit.to_be_bytes().to_vec(),
+            tag.as_str(),
+            time
+        ],
+    )?;
+    Ok(())
+}
+
+fn decode_u128(bytes: &[u8]) -> u128 {
+    let mut buff = [0u8; 16];
+    buff.copy_from_slice(bytes);
+    u128::from_be_bytes(buff)
+}
+
+/// The running balance (`sum(credit) - sum(debit)`) for `account` in `currency`, over every
+/// ledger entry posted so far.
+pub fn account_balance(conn: &Connection, account: &str, currency: &str) -> rusqlite::Result<i128> {
+    let rows: Vec<(Vec<u8>, Vec<u8>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT credit, debit FROM ledger_entries WHERE account = ?1 AND currency = ?2;",
+        )?;
+        let rows = stmt.query_map(params![account, currency], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mut balance: i128 = 0;
+    for (credit, debit) in rows {
+        balance += decode_u128(&credit) as i128;
+        balance -= decode_u128(&debit) as i128;
+    }
+    Ok(balance)
+}
+
+/// Error reconciling the ledger's derived balance for a friend channel against the live balance
+/// held in `mutual_credits`.
+#[derive(Debug)]
+pub enum ReconcileError {
+    Sql(rusqlite::Error),
+    /// The ledger-derived balance and the live channel balance disagree.
+    Mismatch { ledger_balance: i128, live_balance: i128 },
+}
+
+impl From<rusqlite::Error> for ReconcileError {
+    fn from(e: rusqlite::Error) -> Self {
+        ReconcileError::Sql(e)
+    }
+}
+
+/// Confirm that the ledger's derived balance for `friend_public_key`/`currency` (an account name
+/// of the form `"friend:<friend_public_key hex>"`, see [`friend_account_name`]) agrees with the
+/// live balance in `mutual_credits`. Intended to be run periodically as a consistency check, not
+/// on the hot path of applying a move token.
+pub fn reconcile_friend_balance(
+    conn: &Connection,
+    friend_public_key: &[u8],
+    currency: &str,
+) -> Result<(), ReconcileError> {
+    let account = friend_account_name(friend_public_key);
+    let ledger_balance = account_balance(conn, &account, currency)?;
+
+    let live_balance: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT balance FROM mutual_credits WHERE friend_public_key = ?1 AND currency = ?2;",
+            params![friend_public_key, currency],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let live_balance = match live_balance {
+        Some(bytes) => decode_u128(&bytes) as i128,
+        None => 0,
+    };
+
+    if ledger_balance != live_balance {
+        return Err(ReconcileError::Mismatch {
+            ledger_balance,
+            live_balance,
+        });
+    }
+
+    Ok(())
+}
+
+/// The conventional ledger account name for a friend channel, keyed by its public key.
+pub fn friend_account_name(friend_public_key: &[u8]) -> String {
+    let mut hex = String::with_capacity(friend_public_key.len() * 2);
+    for byte in friend_public_key {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    format!("friend:{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    #[test]
+    fn test_account_balance_nets_credits_and_debits() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // `create_database` already created `ledger_entries` (migration step 5).
+        create_database(&mut conn).unwrap();
+
+        insert_event(&conn, 1);
+        insert_event(&conn, 2);
+
+        post_entry(&conn, &1i64.to_be_bytes(), "friend:aa", "FST", 100, 0, LedgerTag::Invoice, 10).unwrap();
+        post_entry(&conn, &2i64.to_be_bytes(), "friend:aa", "FST", 0, 30, LedgerTag::Payment, 20).unwrap();
+
+        assert_eq!(account_balance(&conn, "friend:aa", "FST").unwrap(), 70);
+    }
+
+    fn insert_event(conn: &Connection, counter: i64) {
+        conn.execute(
+            "INSERT INTO events (counter, time, event_type, app_public_key, app_name)
+             VALUES (?1, 0, 'P', ?2, 'app');",
+            params![counter.to_be_bytes().to_vec(), vec![0u8; 32]],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_reconcile_detects_mismatch() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // `create_database` already created `ledger_entries` (migration step 5).
+        create_database(&mut conn).unwrap();
+
+        insert_event(&conn, 1);
+        let friend_public_key = vec![0xaa; 32];
+        post_entry(&conn, &1i64.to_be_bytes(), &friend_account_name(&friend_public_key), "FST", 50, 0, LedgerTag::Invoice, 10)
+            .unwrap();
+
+        let result = reconcile_friend_balance(&conn, &friend_public_key, "FST");
+        assert!(matches!(
+            result,
+            Err(ReconcileError::Mismatch { ledger_balance: 50, live_balance: 0 })
+        ));
+    }
+}
@@ -0,0 +1,1050 @@
+use rusqlite::{params, Connection};
+
+/// A single schema migration step. Most steps are a straightforward DDL change and fit in a
+/// plain SQL string; a step needs the `Fn` variant instead when it has to read/transform
+/// existing rows in a way raw SQL can't express (e.g. backfilling a new column from another
+/// table).
+pub enum MigrationStep {
+    Sql(&'static str),
+    Fn(fn(&rusqlite::Transaction) -> rusqlite::Result<()>),
+}
+
+/// Ordered migration steps. `MIGRATIONS[i]` upgrades a database from version `i` to `i + 1`.
+/// Indices are the migration identity: never reorder, renumber, or delete an existing entry —
+/// only ever append. Step 0 is the full schema that used to live directly in
+/// `create_database()`; a brand new database is simply one that starts at version 0 and runs
+/// every step in order, same as an old one being brought up to date.
+const MIGRATIONS: &[MigrationStep] = &[
+    MigrationStep::Fn(step_0_create_schema),
+    MigrationStep::Fn(migrate_v1_to_v2),
+    MigrationStep::Fn(migrate_v2_to_v3),
+    MigrationStep::Fn(migrate_v3_to_v4),
+    MigrationStep::Sql(crate::history::CREATE_TRANSACTION_HISTORY_VIEW),
+    MigrationStep::Sql(crate::ledger::CREATE_LEDGER_ENTRIES_TABLE),
+    MigrationStep::Sql(crate::invoice_commitments::CREATE_PENDING_COMMITMENTS_TABLE),
+];
+
+/// The schema version produced by running every step in `MIGRATIONS`.
+pub const CURRENT_DB_VERSION: i64 = MIGRATIONS.len() as i64;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sql(rusqlite::Error),
+    /// The database was created by a newer version of this software than the one currently
+    /// running. We refuse to guess how to downgrade it.
+    FutureVersion(i64),
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sql(e)
+    }
+}
+
+/// Step 0: the original `create_database()` schema, verbatim, plus the `schema_version` table
+/// that the migration engine itself relies on. Kept as one big step (rather than split further)
+/// because it predates the migration subsystem and every database in the wild is already past
+/// it.
+fn step_0_create_schema(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    // Single row is enforced in this table according to https://stackoverflow.com/a/33104119
+    tx.execute(
+        "CREATE TABLE schema_version(
+             id                       INTEGER PRIMARY KEY CHECK (id == 0),
+             version                  INTEGER NOT NULL
+            );",
+        params![],
+    )?;
+    tx.execute(
+        "INSERT INTO schema_version (id, version) VALUES (0, 0);",
+        params![],
+    )?;
+
+    tx.execute("PRAGMA foreign_keys = ON;", params![])?;
+
+    tx.execute(
+        "CREATE TABLE applications (
+             app_public_key             BLOB NOT NULL PRIMARY KEY,
+             app_name                   TEXT NOT NULL UNIQUE,
+             last_online                INTEGER NOT NULL,
+             is_enabled                 BOOL NOT NULL,
+
+             perm_info_docs             BOOL NOT NULL,
+             perm_info_friends          BOOL NOT NULL,
+             perm_buy                   BOOL NOT NULL,
+             perm_sell                  BOOL NOT NULL,
+             perm_config_card           BOOL NOT NULL,
+             perm_config_access         BOOL NOT NULL,
+
+             FOREIGN KEY(app_public_key)
+                REFERENCES applications(app_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_applications ON applications(app_public_key, app_name);",
+        params![],
+    )?;
+
+    // Single row is enforced in this table according to https://stackoverflow.com/a/33104119
+    tx.execute(
+        "CREATE TABLE node(
+             id                       INTEGER PRIMARY KEY CHECK (id == 0), -- enforce single row
+             version                  INTEGER NOT NULL,  -- Database version
+             local_public_key         BLOB NOT NULL
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE friends(
+             friend_public_key          BLOB NOT NULL PRIMARY KEY,
+             friend_name                TEXT NOT NULL UNIQUE,
+             -- Friend's name
+             is_enabled                 BOOL NOT NULL,
+             -- Do we allow connectivity with this friend?
+             is_consistent              BOOL NOT NULL
+             -- Is the channel with this friend consistent?
+            );",
+        params![],
+    )?;
+
+    // friend public key index:
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_friends_friend_public_key ON friends(friend_public_key);",
+        params![],
+    )?;
+
+    // public name index:
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_friends_name ON friends(friend_name);",
+        params![],
+    )?;
+
+    // Relays list we have received from the friend
+    // We use those relays to connect to the friend.
+    tx.execute(
+        "CREATE TABLE received_friend_relays(
+             friend_public_key        BLOB NOT NULL,
+             relay_public_key         BLOB NOT NULL,
+             port                     BLOB NOT NULL,
+             address                  TEXT NOT NULL,
+             PRIMARY KEY(friend_public_key, relay_public_key),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES friends(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_received_friend_relays ON received_friend_relays(friend_public_key, relay_public_key);",
+        params![],
+    )?;
+
+    // Relays list we have sent to the friend
+    // The friend will use those relays to connect to us
+    tx.execute(
+        "CREATE TABLE sent_friend_relays(
+             friend_public_key        BLOB NOT NULL,
+             relay_public_key         BLOB NOT NULL,
+             port                     BLOB NOT NULL,
+             address                  TEXT NOT NULL,
+             is_remove                BOOL NOT NULL,
+             -- Is marked for removal?
+             generation               BLOB,
+             -- At which generation this relay was added/removed?
+             -- NULL if change was already acked.
+             CHECK (NOT (is_remove == true AND generation == NULL)),
+             PRIMARY KEY(friend_public_key, relay_public_key),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES friends(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_sent_friend_relays ON sent_friend_relays(friend_public_key, relay_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX idx_sent_friend_relays_generation ON sent_friend_relays(generation);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE currency_configs(
+             friend_public_key          BLOB NOT NULL,
+             currency                   TEXT NOT NULL,
+             rate                       BLOB NOT NULL,
+             remote_max_debt            BLOB NOT NULL,
+             is_open                    BOOL NOT NULL,
+             PRIMARY KEY(friend_public_key, currency),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES friends(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_currency_configs ON currency_configs(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE consistent_channels(
+             friend_public_key          BLOB NOT NULL PRIMARY KEY,
+             is_consistent              BOOL NOT NULL
+                                        CHECK (is_consistent == true)
+                                        DEFAULT true,
+             move_token_counter         BLOB NOT NULL,
+             is_incoming                BOOL NOT NULL,
+
+             FOREIGN KEY(friend_public_key, is_consistent)
+                REFERENCES friends(friend_public_key, is_consistent)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_consistent_channels ON consistent_channels(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE consistent_channels_incoming(
+             friend_public_key      BLOB NOT NULL PRIMARY KEY,
+             is_incoming            BOOL NOT NULL
+                                    CHECK (is_incoming == true)
+                                    DEFAULT true,
+             old_token              BLOB NOT NULL,
+             new_token              BLOB NOT NULL,
+
+             FOREIGN KEY(friend_public_key, is_incoming)
+                REFERENCES consistent_channels(friend_public_key, is_incoming)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_consistent_channels_incoming ON consistent_channels_incoming(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE consistent_channels_outgoing(
+             friend_public_key          BLOB NOT NULL PRIMARY KEY,
+             is_incoming                BOOL NOT NULL
+                                        CHECK (is_incoming == false)
+                                        DEFAULT false,
+             move_token_out             BLOB NOT NULL,
+
+             -- Do we save a previously received hashed incoming move token?
+             is_with_incoming           BOOL NOT NULL,
+
+             FOREIGN KEY(friend_public_key, is_incoming)
+                REFERENCES consistent_channels(friend_public_key, is_incoming)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_consistent_channels_outgoing ON consistent_channels_outgoing(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE consistent_channels_outgoing_with_incoming(
+             friend_public_key          BLOB NOT NULL PRIMARY KEY,
+             is_with_incoming           BOOL NOT NULL
+                                        CHECK (is_with_incoming == true)
+                                        DEFAULT true,
+
+             -- Data saved for last incoming move token.
+             -- The list of balances for the last incoming move token is saved in a separate table.
+             old_token                  BLOB NOT NULL,
+             move_token_counter         BLOB NOT NULL,
+             new_token                  BLOB NOT NULL,
+
+             FOREIGN KEY(friend_public_key, is_with_incoming)
+                REFERENCES consistent_channels_outgoing(friend_public_key, is_with_incoming)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_consistent_channels_outgoing_with_incoming ON consistent_channels_outgoing_with_incoming(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE last_incoming_balances(
+             friend_public_key          BLOB NOT NULL,
+             currency                   TEXT NOT NULL,
+
+             balance                    BLOB NOT NULL,
+             local_pending_debt         BLOB NOT NULL,
+             remote_pending_debt        BLOB NOT NULL,
+             in_fees                    BLOB NOT NULL,
+             out_fees                   BLOB NOT NULL,
+
+             PRIMARY KEY(friend_public_key, currency),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES consistent_channels_outgoing_with_incoming(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_last_incoming_balances ON last_incoming_balances(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE inconsistent_channels(
+             friend_public_key                      BLOB NOT NULL PRIMARY KEY,
+             is_consistent                          BOOL NOT NULL
+                                                    CHECK (is_consistent == false)
+                                                    DEFAULT false,
+             -- Last seen incoming move token:
+             opt_move_token_in                      BLOB,
+             -- Local reset terms:
+             local_reset_token                      BLOB NOT NULL,
+             local_reset_move_token_counter         BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, is_consistent)
+                REFERENCES friends(friend_public_key, is_consistent)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_inconsistent_channels ON inconsistent_channels(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE local_reset_terms(
+             friend_public_key              BLOB NOT NULL,
+             currency                       TEXT NOT NULL,
+             balance                        BLOB NOT NULL,
+             in_fees                        BLOB NOT NULL,
+             out_fees                       BLOB NOT NULL,
+             PRIMARY KEY(friend_public_key, currency),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES inconsistent_channels(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX local_reset_terms_idx ON local_reset_terms(friend_public_key, currency);",
+        params![],
+    )?;
+
+    // Inconsistent channels that contain remote reset terms.
+    tx.execute(
+        "CREATE TABLE inconsistent_channels_with_remote(
+             friend_public_key                      BLOB NOT NULL PRIMARY KEY,
+             -- Remote reset terms:
+             remote_reset_token                     BLOB NOT NULL,
+             remote_reset_move_token_counter        BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key)
+                REFERENCES inconsistent_channels(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX inconsistent_channels_with_remote_idx ON
+        inconsistent_channels_with_remote(friend_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE remote_reset_terms(
+             friend_public_key              BLOB NOT NULL,
+             currency                       TEXT NOT NULL,
+             balance                        BLOB NOT NULL,
+             in_fees                        BLOB NOT NULL,
+             out_fees                       BLOB NOT NULL,
+             PRIMARY KEY(friend_public_key, currency),
+             FOREIGN KEY(friend_public_key)
+                REFERENCES inconsistent_channels_with_remote(friend_public_key)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX remote_reset_terms_idx ON remote_reset_terms(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE local_currencies(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             FOREIGN KEY(friend_public_key)
+                REFERENCES consistent_channels(friend_public_key)
+                ON DELETE CASCADE,
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES currency_configs(friend_public_key, currency)
+                ON DELETE CASCADE,
+             PRIMARY KEY(friend_public_key, currency)
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_local_currencies ON local_currencies(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE remote_currencies(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             FOREIGN KEY(friend_public_key)
+                REFERENCES consistent_channels(friend_public_key)
+                ON DELETE CASCADE,
+             PRIMARY KEY(friend_public_key, currency)
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_remote_currencies ON remote_currencies(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE mutual_credits(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             balance                  BLOB NOT NULL,
+             local_pending_debt       BLOB NOT NULL,
+             remote_pending_debt      BLOB NOT NULL,
+             in_fees                  BLOB NOT NULL,
+             out_fees                 BLOB NOT NULL,
+             PRIMARY KEY(friend_public_key, currency)
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES local_currencies(friend_public_key, currency)
+                ON DELETE CASCADE
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES remote_currencies(friend_public_key, currency)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_mutual_credits ON mutual_credits(friend_public_key, currency);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE local_open_transactions(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             src_hashed_lock          BLOB NOT NULL,
+             route                    BLOB NOT NULL,
+             dest_payment             BLOB NOT NULL,
+             total_dest_payment       BLOB NOT NULL,
+             invoice_hash             BLOB NOT NULL,
+             hmac                     BLOB NOT NULL,
+             left_fees                BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES mutual_credits(friend_public_key, currency)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_local_open_transactions ON local_open_transactions(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE remote_open_transactions(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             src_hashed_lock          BLOB NOT NULL,
+             route                    BLOB NOT NULL,
+             dest_payment             BLOB NOT NULL,
+             total_dest_payment       BLOB NOT NULL,
+             invoice_hash             BLOB NOT NULL,
+             hmac                     BLOB NOT NULL,
+             left_fees                BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES mutual_credits(friend_public_key, currency)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_remote_open_transactions ON remote_open_transactions(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE pending_user_requests(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             generation               BLOB NOT NULL UNIQUE,
+             src_hashed_lock          BLOB NOT NULL,
+             route                    BLOB NOT NULL,
+             dest_payment             BLOB NOT NULL,
+             total_dest_payment       BLOB NOT NULL,
+             invoice_hash             BLOB NOT NULL,
+             hmac                     BLOB NOT NULL,
+             left_fees                BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES mutual_credits(friend_public_key, currency)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_user_requests_request_id ON pending_user_requests(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_user_requests_generation ON pending_user_requests(generation);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE pending_requests(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             generation               BLOB NOT NULL UNIQUE,
+             src_hashed_lock          BLOB NOT NULL,
+             route                    BLOB NOT NULL,
+             dest_payment             BLOB NOT NULL,
+             total_dest_payment       BLOB NOT NULL,
+             invoice_hash             BLOB NOT NULL,
+             hmac                     BLOB NOT NULL,
+             left_fees                BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES mutual_credits(friend_public_key, currency)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_requests_request_id ON pending_requests(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_requests_generation ON pending_requests(generation);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE pending_backwards(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             generation               BLOB NOT NULL UNIQUE,
+             backwards_type           TEXT CHECK (backwards_type IN ('R', 'C')) NOT NULL,
+             -- R: Response, C: Cancel
+             FOREIGN KEY(friend_public_key, currency)
+                REFERENCES mutual_credits(friend_public_key, currency)
+                ON DELETE CASCADE,
+             FOREIGN KEY(request_id)
+                REFERENCES pending_requests(request_id)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_backwards_request_id ON pending_backwards(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_backwards_generation ON pending_backwards(generation);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE pending_backwards_responses(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             backwards_type           TEXT NOT NULL
+                                      CHECK (backwards_type == 'R')
+                                      DEFAULT 'R',
+             src_hashed_lock          BLOB NOT NULL,
+             serial_num               BLOB NOT NULL,
+             signature                BLOB NOT NULL,
+             FOREIGN KEY(friend_public_key, currency, request_id, backwards_type)
+                REFERENCES pending_backwards(friend_public_key, currency, request_id, backwards_type)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_backwards_responses ON pending_backwards_responses(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE pending_backwards_cancels(
+             friend_public_key        BLOB NOT NULL,
+             currency                 TEXT NOT NULL,
+             request_id               BLOB NOT NULL PRIMARY KEY,
+             backwards_type           TEXT NOT NULL
+                                      CHECK (backwards_type == 'C')
+                                      DEFAULT 'C',
+             FOREIGN KEY(friend_public_key, currency, request_id, backwards_type)
+                REFERENCES pending_backwards(friend_public_key, currency, request_id, backwards_type)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_pending_backwards_cancels ON pending_backwards_cancels(request_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE relays(
+             relay_public_key         BLOB NOT NULL PRIMARY KEY,
+             address                  TEXT NOT NULL,
+             relay_name               TEXT NOT NULL
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_relays ON relays(relay_public_key);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE index_servers(
+             index_public_key         BLOB NOT NULL PRIMARY KEY,
+             address                  TEXT NOT NULL,
+             index_name               TEXT NOT NULL
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_index_servers ON index_servers(index_public_key);",
+        params![],
+    )?;
+
+    // Ongoing payments that were not yet finalized
+    // TODO: Complete open payments information:
+    // - Pending requests/responses?
+    // - Stage information? (See state.rs in funder)
+    tx.execute(
+        "CREATE TABLE open_payments(
+            payment_id          BLOB NOT NULL PRIMARY KEY,
+            dest_public_key     BLOB NOT NULL,
+            currency            TEXT NOT NULL,
+            amount              BLOB NOT NULL,
+            description         TEXT NOT NULL,
+            src_plain_lock      BLOB NOT NULL
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_open_payments ON open_payments(payment_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE open_invoices(
+             invoice_id      BLOB NOT NULL PRIMARY KEY,
+             psk             BLOB NOT NULL,
+             description     TEXT NOT NULL,
+             data_hash       BLOB NOT NULL,
+             currency        TEXT NOT NULL,
+             opt_amount      BLOB
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_open_invoices ON open_invoices(invoice_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE open_invoices_relays(
+             invoice_id      BLOB NOT NULL,
+             relay_address   TEXT NOT NULL,
+             relay_port      BLOB NOT NULL,
+             PRIMARY KEY(invoice_id, relay_address),
+             FOREIGN KEY(invoice_id)
+                 REFERENCES open_invoices(invoice_id)
+                 ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_open_invoices_relays ON open_invoices_relays(invoice_id, relay_address);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE invoice_actions(
+             invoice_id             BLOB NOT NULL,
+             action_id              BLOB NOT NULL,
+             description            TEXT NOT NULL,
+             invoice_hash           BLOB NOT NULL UNIQUE,
+             -- invoiceHash = sha512/256(action_id || hash(description) || data_hash)
+             opt_src_hashed_lock    BLOB,
+             -- src_hashed_lock is provided by the buyer/payer during the commitment.
+             PRIMARY KEY(invoice_id, action_id),
+             FOREIGN KEY(invoice_id)
+                 REFERENCES open_invoices(invoice_id)
+                 ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_invoice_actions ON invoice_actions(invoice_id, action_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE invoice_actions_requests(
+             invoice_id             BLOB NOT NULL,
+             action_id              BLOB NOT NULL,
+             request_id             BLOB NOT NULL PRIMARY KEY,
+             FOREIGN KEY(request_id)
+                 REFERENCES remote_open_transactions(request_id)
+                 ON DELETE RESTRICT,
+             FOREIGN KEY(invoice_id, action_id)
+                 REFERENCES invoice_actions(invoice_id, action_id)
+                 ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE INDEX idx_actions_requests ON invoice_actions_requests(invoice_id, action_id);",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_actions_requests_request_id ON invoice_actions_requests(request_id);",
+        params![],
+    )?;
+
+    // Documents table, allowing total order on all items payments and invoices
+    tx.execute(
+        "CREATE TABLE events(
+             counter      BLOB NOT NULL PRIMARY KEY,
+             time         INTEGER NOT NULL,
+             event_type   TEXT CHECK (event_type IN ('P', 'I', 'R')) NOT NULL,
+             -- Event type: P: Payment, I: Invoice, R: Friend Removal
+             -- Information about the application that trigerred this event:
+             app_public_key             BLOB NOT NULL,
+             app_name                   TEXT NOT NULL
+            );",
+        params![],
+    )?;
+
+    // Balances table, showing the exact balance for every event.
+    // The numbers shown represent the numbers right after the event occurred.
+    tx.execute(
+        "CREATE TABLE event_balances(
+              counter      BLOB NOT NULL,
+              currency     TEXT NOT NULL,
+              amount       BLOB NOT NULL,
+              in_fees      BLOB NOT NULL,
+              out_fees     BLOB NOT NULL,
+              event_type   TEXT CHECK (event_type IN ('P', 'I', 'F')) NOT NULL,
+              -- Event type: P: Payment, I: Invoice, F: Friend inconsistency
+
+              PRIMARY KEY(counter, currency)
+              FOREIGN KEY(counter)
+                 REFERENCES events(counter)
+                 ON DELETE CASCADE
+             );",
+        params![],
+    )?;
+
+    tx.execute(
+        "CREATE TABLE payment_events(
+             counter             BLOB NOT NULL PRIMARY KEY,
+             event_type          TEXT CHECK (event_type == 'P')
+                                 DEFAULT 'P'
+                                 NOT NULL,
+             response_hash       BLOB NOT NULL,
+             dest_public_key     BLOB NOT NULL,
+             serial_num          BLOB NOT NULL,
+             action_id           BLOB NOT NULL,
+             currency            TEXT NOT NULL,
+             amount              BLOB NOT NULL,
+             description         TEXT NOT NULL,
+             data_hash           BLOB NOT NULL,
+             signature           BLOB NOT NULL,
+             UNIQUE (dest_public_key, serial_num),
+             -- A node can not issue the same invoice sequence number twice
+             FOREIGN KEY(counter, event_type)
+                REFERENCES events(counter, event_type)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    // TODO: Add relevant indexes?
+    // - Search using description?
+    // - Search using counter?
+    // - How to make interface more flexible?
+    //      Allowing user to make interesting queries?
+
+    tx.execute(
+        "CREATE TABLE invoice_events (
+             counter         BLOB NOT NULL PRIMARY KEY,
+             event_type      TEXT CHECK (event_type == 'I')
+                             DEFAULT 'I'
+                             NOT NULL,
+             serial_num      BLOB NOT NULL UNIQUE,
+             currency        TEXT NOT NULL,
+             amount          BLOB NOT NULL,
+             description     TEXT NOT NULL,
+             data_hash       BLOB NOT NULL,
+             FOREIGN KEY(counter, event_type)
+                REFERENCES events(counter, event_type)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    // A case of friend inconsistency or friend removal
+    tx.execute(
+        "CREATE TABLE friend_inconsistency_events (
+             counter                BLOB NOT NULL PRIMARY KEY,
+             event_type             TEXT CHECK (event_type == 'F')
+                                    DEFAULT 'F'
+                                    NOT NULL,
+             friend_public_key      BLOB NOT NULL,
+             friend_name            TEXT NOT NULL,
+             FOREIGN KEY(counter, event_type)
+                REFERENCES events(counter, event_type)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    // Balances (per currency) in case of friend inconsistency event.
+    // Friend inconsistency can be one of: 1. Channel reset, 2. Friend removal, 3. currency removal
+    tx.execute(
+        "CREATE TABLE friend_inconsistency_event_balances (
+             counter                BLOB NOT NULL,
+             currency               TEXT NOT NULL,
+             old_balance            BLOB NOT NULL,
+             old_in_fees            BLOB NOT NULL,
+             old_out_fees           BLOB NOT NULL,
+             new_balance            BLOB NOT NULL,
+             new_in_fees            BLOB NOT NULL,
+             new_out_fees           BLOB NOT NULL,
+             PRIMARY KEY(counter, currency),
+             FOREIGN KEY(counter)
+                REFERENCES friend_inconsistency_events(counter)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+
+    Ok(())
+}
+
+/// v1 -> v2: add `expire_at` expiry timestamps to the tables pruned by the background pruning
+/// sweep. Existing rows, which predate expiry tracking, are defaulted to a far-future timestamp
+/// rather than an already-expired one, so the next pruning sweep does not immediately delete them.
+fn migrate_v1_to_v2(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    const FAR_FUTURE: i64 = 9_999_999_999;
+    tx.execute(
+        &format!("ALTER TABLE open_payments ADD COLUMN expire_at INTEGER NOT NULL DEFAULT {};", FAR_FUTURE),
+        params![],
+    )?;
+    tx.execute(
+        &format!("ALTER TABLE open_invoices ADD COLUMN expire_at INTEGER NOT NULL DEFAULT {};", FAR_FUTURE),
+        params![],
+    )?;
+    tx.execute(
+        &format!("ALTER TABLE local_open_transactions ADD COLUMN expire_at INTEGER NOT NULL DEFAULT {};", FAR_FUTURE),
+        params![],
+    )?;
+    tx.execute(
+        &format!("ALTER TABLE remote_open_transactions ADD COLUMN expire_at INTEGER NOT NULL DEFAULT {};", FAR_FUTURE),
+        params![],
+    )?;
+
+    tx.execute("CREATE INDEX idx_open_payments_expire_at ON open_payments(expire_at);", params![])?;
+    tx.execute("CREATE INDEX idx_open_invoices_expire_at ON open_invoices(expire_at);", params![])?;
+    tx.execute(
+        "CREATE INDEX idx_local_open_transactions_expire_at ON local_open_transactions(expire_at);",
+        params![],
+    )?;
+    tx.execute(
+        "CREATE INDEX idx_remote_open_transactions_expire_at ON remote_open_transactions(expire_at);",
+        params![],
+    )?;
+
+    Ok(())
+}
+
+/// v2 -> v3: add `open_payment_attempts`, so a payment can be tracked as it fans out over
+/// several route/fragment attempts instead of the all-or-nothing single route `open_payments`
+/// implied before.
+fn migrate_v2_to_v3(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE open_payment_attempts(
+             payment_id          BLOB NOT NULL,
+             attempt_index       INTEGER NOT NULL,
+             route               BLOB NOT NULL,
+             fragment_amount     BLOB NOT NULL,
+             request_id          BLOB NOT NULL,
+             status              TEXT CHECK (status IN ('pending', 'success', 'failure'))
+                                  NOT NULL
+                                  DEFAULT 'pending',
+             PRIMARY KEY(payment_id, attempt_index),
+             FOREIGN KEY(payment_id)
+                REFERENCES open_payments(payment_id)
+                ON DELETE CASCADE
+            );",
+        params![],
+    )?;
+    tx.execute(
+        "CREATE UNIQUE INDEX idx_open_payment_attempts_request_id ON open_payment_attempts(request_id);",
+        params![],
+    )?;
+
+    Ok(())
+}
+
+/// v3 -> v4: add `offers`, reusable payment handles that mint a fresh single-use invoice in
+/// `open_invoices` on each use, linked back by the new `open_invoices.offer_id` column.
+fn migrate_v3_to_v4(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    tx.execute(
+        "CREATE TABLE offers(
+             offer_id        BLOB NOT NULL PRIMARY KEY,
+             currency        TEXT NOT NULL,
+             min_amount      BLOB,
+             max_amount      BLOB,
+             description     TEXT NOT NULL,
+             max_uses        INTEGER,
+             use_count       INTEGER NOT NULL DEFAULT 0,
+             last_used       INTEGER,
+             expire_at       INTEGER
+            );",
+        params![],
+    )?;
+    tx.execute("CREATE UNIQUE INDEX idx_offers ON offers(offer_id);", params![])?;
+
+    tx.execute("ALTER TABLE open_invoices ADD COLUMN offer_id BLOB;", params![])?;
+    tx.execute(
+        "CREATE INDEX idx_open_invoices_offer_id ON open_invoices(offer_id);",
+        params![],
+    )?;
+
+    Ok(())
+}
+
+/// Read the schema version of a connection, treating one that hasn't even run step 0 yet
+/// (no `schema_version` table) as version 0.
+fn read_db_version(conn: &Connection) -> rusqlite::Result<i64> {
+    let table_exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version');",
+        params![],
+        |row| row.get(0),
+    )?;
+
+    if !table_exists {
+        return Ok(0);
+    }
+
+    conn.query_row("SELECT version FROM schema_version WHERE id = 0;", params![], |row| {
+        row.get(0)
+    })
+}
+
+/// Bring a database up to `CURRENT_DB_VERSION`, applying any pending steps from `MIGRATIONS` in
+/// order. Each step runs in its own transaction together with the `schema_version` bump, so a
+/// crash mid-migration leaves the database at a consistent, still-migratable version rather
+/// than a half-upgraded schema. Used both to create a brand new database (starting from
+/// version 0) and to upgrade an existing one.
+pub fn migrate_database(conn: &mut Connection) -> Result<(), MigrationError> {
+    let mut version = read_db_version(conn)?;
+
+    if version > CURRENT_DB_VERSION {
+        return Err(MigrationError::FutureVersion(version));
+    }
+
+    while version < CURRENT_DB_VERSION {
+        let step = &MIGRATIONS[version as usize];
+
+        let tx = conn.transaction()?;
+        match step {
+            MigrationStep::Sql(sql) => tx.execute_batch(sql)?,
+            MigrationStep::Fn(f) => f(&tx)?,
+        }
+        tx.execute(
+            "UPDATE schema_version SET version = ?1 WHERE id = 0;",
+            params![version + 1],
+        )?;
+        tx.commit()?;
+
+        version += 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    #[test]
+    fn test_migrate_fresh_database_reaches_current_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        assert_eq!(read_db_version(&conn).unwrap(), CURRENT_DB_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        migrate_database(&mut conn).unwrap();
+        assert_eq!(read_db_version(&conn).unwrap(), CURRENT_DB_VERSION);
+    }
+}
@@ -0,0 +1,126 @@
+use rusqlite::{params, Connection};
+
+#[derive(Debug)]
+pub enum OfferError {
+    Sql(rusqlite::Error),
+    /// The offer does not exist, has already reached `max_uses`, or has expired.
+    OfferUnavailable,
+}
+
+impl From<rusqlite::Error> for OfferError {
+    fn from(e: rusqlite::Error) -> Self {
+        OfferError::Sql(e)
+    }
+}
+
+/// Invoke `offer_id`, minting a fresh single-use invoice linked back to it, bumping `use_count`
+/// and `last_used`. Fails if the offer does not exist, is already at `max_uses`, or has expired
+/// as of `now`. Takes `tx` rather than a bare `&Connection`, like
+/// `invoice_commitments.rs::create_action`/`reap_expired_invoices`, so the `max_uses` check and
+/// the insert/update it gates can't straddle two overlapping invocations and over-redeem a
+/// bounded-use offer.
+pub fn invoke_offer(
+    tx: &rusqlite::Transaction,
+    offer_id: &[u8],
+    invoice_id: &[u8],
+    psk: &[u8],
+    description: &str,
+    data_hash: &[u8],
+    currency: &str,
+    opt_amount: Option<&[u8]>,
+    invoice_expire_at: i64,
+    now: i64,
+) -> Result<(), OfferError> {
+    let row = tx
+        .query_row(
+            "SELECT max_uses, use_count, expire_at FROM offers WHERE offer_id = ?1;",
+            params![offer_id],
+            |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            },
+        )
+        .map_err(|_| OfferError::OfferUnavailable)?;
+
+    let (max_uses, use_count, expire_at) = row;
+
+    if let Some(expire_at) = expire_at {
+        if now >= expire_at {
+            return Err(OfferError::OfferUnavailable);
+        }
+    }
+
+    if let Some(max_uses) = max_uses {
+        if use_count >= max_uses {
+            return Err(OfferError::OfferUnavailable);
+        }
+    }
+
+    tx.execute(
+        "INSERT INTO open_invoices (invoice_id, psk, description, data_hash, currency, opt_amount, expire_at, offer_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8);",
+        params![invoice_id, psk, description, data_hash, currency, opt_amount, invoice_expire_at, offer_id],
+    )?;
+
+    tx.execute(
+        "UPDATE offers SET use_count = use_count + 1, last_used = ?1 WHERE offer_id = ?2;",
+        params![now, offer_id],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    fn dummy_offer(conn: &Connection, offer_id: &[u8], max_uses: Option<i64>, expire_at: Option<i64>) {
+        conn.execute(
+            "INSERT INTO offers (offer_id, currency, description, max_uses, expire_at)
+             VALUES (?1, 'FST', 'desc', ?2, ?3);",
+            params![offer_id, max_uses, expire_at],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_invoke_offer_mints_invoice() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let offer_id = vec![0u8; 16];
+        dummy_offer(&conn, &offer_id, None, None);
+
+        let tx = conn.transaction().unwrap();
+        invoke_offer(&tx, &offer_id, &[1u8; 16], &[2u8; 32], "desc", &[3u8; 32], "FST", None, 9999999999, 100)
+            .unwrap();
+        tx.commit().unwrap();
+
+        let use_count: i64 = conn
+            .query_row("SELECT use_count FROM offers WHERE offer_id = ?1;", params![offer_id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(use_count, 1);
+    }
+
+    #[test]
+    fn test_invoke_offer_rejects_after_max_uses() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let offer_id = vec![0u8; 16];
+        dummy_offer(&conn, &offer_id, Some(1), None);
+
+        {
+            let tx = conn.transaction().unwrap();
+            invoke_offer(&tx, &offer_id, &[1u8; 16], &[2u8; 32], "desc", &[3u8; 32], "FST", None, 9999999999, 100)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let tx = conn.transaction().unwrap();
+        let result = invoke_offer(&tx, &offer_id, &[4u8; 16], &[2u8; 32], "desc", &[3u8; 32], "FST", None, 9999999999, 100);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Status of a single route/fragment attempt within a multi-route payment, mirroring the
+/// `status` check constraint on `open_payment_attempts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttemptStatus {
+    Pending,
+    Success,
+    Failure,
+}
+
+impl AttemptStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            AttemptStatus::Pending => "pending",
+            AttemptStatus::Success => "success",
+            AttemptStatus::Failure => "failure",
+        }
+    }
+
+    fn from_str(s: &str) -> Self {
+        match s {
+            "pending" => AttemptStatus::Pending,
+            "success" => AttemptStatus::Success,
+            "failure" => AttemptStatus::Failure,
+            _ => unreachable!("status check constraint guarantees one of the three values"),
+        }
+    }
+}
+
+/// Record a new route/fragment attempt for `payment_id`, assigning it the next unused
+/// `attempt_index`.
+pub fn record_attempt(
+    conn: &Connection,
+    payment_id: &[u8],
+    route: &[u8],
+    fragment_amount: &[u8],
+    request_id: &[u8],
+) -> rusqlite::Result<i64> {
+    let next_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(attempt_index), -1) + 1 FROM open_payment_attempts WHERE payment_id = ?1;",
+        params![payment_id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO open_payment_attempts (payment_id, attempt_index, route, fragment_amount, request_id, status)
+         VALUES (?1, ?2, ?3, ?4, ?5, 'pending');",
+        params![payment_id, next_index, route, fragment_amount, request_id],
+    )?;
+
+    Ok(next_index)
+}
+
+/// Mark the attempt identified by `request_id` as having succeeded or failed.
+pub fn set_attempt_status(conn: &Connection, request_id: &[u8], status: AttemptStatus) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE open_payment_attempts SET status = ?1 WHERE request_id = ?2;",
+        params![status.as_str(), request_id],
+    )?;
+    Ok(())
+}
+
+pub fn attempt_status(conn: &Connection, request_id: &[u8]) -> rusqlite::Result<Option<AttemptStatus>> {
+    conn.query_row(
+        "SELECT status FROM open_payment_attempts WHERE request_id = ?1;",
+        params![request_id],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+    .map(|opt_status| opt_status.map(|status| AttemptStatus::from_str(&status)))
+}
+
+/// The total amount still owed for `payment_id`: `amount` minus the fragment amounts of every
+/// attempt that is `pending` or already `success` (a `failure`'d attempt's fragment is up for
+/// grabs again by a retry).
+pub fn remaining_unpaid_amount(conn: &Connection, payment_id: &[u8], amount: u128) -> rusqlite::Result<u128> {
+    let fragment_amounts: Vec<Vec<u8>> = {
+        let mut stmt = conn.prepare(
+            "SELECT fragment_amount FROM open_payment_attempts
+             WHERE payment_id = ?1 AND status != 'failure';",
+        )?;
+        let rows = stmt.query_map(params![payment_id], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<Vec<u8>>>>()?
+    };
+
+    let accounted_for: u128 = fragment_amounts
+        .iter()
+        .map(|bytes| {
+            let mut buff = [0u8; 16];
+            buff.copy_from_slice(bytes);
+            u128::from_be_bytes(buff)
+        })
+        .sum();
+
+    Ok(amount.saturating_sub(accounted_for))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    fn dummy_payment(conn: &Connection, payment_id: &[u8]) {
+        conn.execute(
+            "INSERT INTO open_payments (payment_id, dest_public_key, currency, amount, description, src_plain_lock, expire_at)
+             VALUES (?1, ?1, 'FST', ?1, 'desc', ?1, 9999999999);",
+            params![payment_id],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_record_and_complete_attempt() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let payment_id = vec![0u8; 16];
+        dummy_payment(&conn, &payment_id);
+
+        let fragment_amount = 100u128.to_be_bytes().to_vec();
+        let request_id = vec![1u8; 16];
+        let index = record_attempt(&conn, &payment_id, &[2u8; 8], &fragment_amount, &request_id).unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(attempt_status(&conn, &request_id).unwrap(), Some(AttemptStatus::Pending));
+
+        set_attempt_status(&conn, &request_id, AttemptStatus::Success).unwrap();
+        assert_eq!(attempt_status(&conn, &request_id).unwrap(), Some(AttemptStatus::Success));
+    }
+
+    #[test]
+    fn test_remaining_unpaid_amount_ignores_failed_attempts() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let payment_id = vec![0u8; 16];
+        dummy_payment(&conn, &payment_id);
+
+        let request_id_a = vec![1u8; 16];
+        record_attempt(&conn, &payment_id, &[2u8; 8], &40u128.to_be_bytes(), &request_id_a).unwrap();
+        set_attempt_status(&conn, &request_id_a, AttemptStatus::Failure).unwrap();
+
+        let request_id_b = vec![3u8; 16];
+        record_attempt(&conn, &payment_id, &[4u8; 8], &30u128.to_be_bytes(), &request_id_b).unwrap();
+
+        assert_eq!(remaining_unpaid_amount(&conn, &payment_id, 100u128).unwrap(), 70u128);
+    }
+}
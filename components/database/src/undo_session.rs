@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use rusqlite::{Connection, Savepoint};
+
+/// Enough information to re-offer a pending request for inclusion in a later consistent state,
+/// after the session that consumed it got rolled back instead of pushed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnappliedRequest {
+    pub friend_public_key: Vec<u8>,
+    pub currency: String,
+    pub request_id: Vec<u8>,
+    pub generation: Vec<u8>,
+}
+
+/// In-memory queue of pending requests that were consumed (removed from `pending_requests` /
+/// `pending_user_requests`) by a session that never got pushed. Kept outside the database so
+/// that an aborted session leaves no trace to clean up: the queue is the only record that the
+/// request still needs to be re-applied.
+#[derive(Default)]
+pub struct UnappliedQueue {
+    entries: HashMap<(Vec<u8>, Vec<u8>), UnappliedRequest>,
+}
+
+impl UnappliedQueue {
+    pub fn new() -> Self {
+        UnappliedQueue {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Re-offer a request that was consumed by a rolled-back session, so it is considered again
+    /// the next time a consistent state is built.
+    pub fn offer(&mut self, request: UnappliedRequest) {
+        let key = (request.request_id.clone(), request.generation.clone());
+        self.entries.insert(key, request);
+    }
+
+    /// All currently queued requests, for the funder to attempt re-applying.
+    pub fn pending(&self) -> impl Iterator<Item = &UnappliedRequest> {
+        self.entries.values()
+    }
+
+    /// Remove a request from the queue once it was successfully re-applied in a later,
+    /// committed session.
+    pub fn remove(&mut self, request_id: &[u8], generation: &[u8]) {
+        self.entries
+            .remove(&(request_id.to_vec(), generation.to_vec()));
+    }
+}
+
+/// An EOSIO `database::session`-style undo session: a savepoint paired with bookkeeping of the
+/// pending requests it consumed along the way. `push()` commits the savepoint and discards that
+/// bookkeeping. Dropping the session without pushing it rolls back to the savepoint *and*
+/// re-offers every consumed request into `queue`, so a channel reset mid-operation never
+/// silently drops a user's in-flight request.
+pub struct UndoSession<'conn, 'q> {
+    savepoint: Option<Savepoint<'conn>>,
+    queue: &'q mut UnappliedQueue,
+    consumed_requests: Vec<UnappliedRequest>,
+    committed: bool,
+}
+
+impl<'conn, 'q> UndoSession<'conn, 'q> {
+    pub fn new(conn: &'conn mut Connection, queue: &'q mut UnappliedQueue) -> rusqlite::Result<Self> {
+        let savepoint = conn.savepoint()?;
+        Ok(UndoSession {
+            savepoint: Some(savepoint),
+            queue,
+            consumed_requests: Vec::new(),
+            committed: false,
+        })
+    }
+
+    /// The underlying connection, for issuing statements within this session's savepoint.
+    pub fn conn(&self) -> &Connection {
+        self.savepoint.as_ref().expect("UndoSession used after push()")
+    }
+
+    /// Record that this session consumed (deleted) a pending request, so that it is re-offered
+    /// into `queue` if the session is rolled back instead of pushed.
+    pub fn mark_request_consumed(&mut self, request: UnappliedRequest) {
+        self.consumed_requests.push(request);
+    }
+
+    /// Commit the session. Requests recorded via `mark_request_consumed` are now durably
+    /// applied and will not be re-offered.
+    pub fn push(mut self) -> rusqlite::Result<()> {
+        let savepoint = self
+            .savepoint
+            .take()
+            .expect("UndoSession::push called twice");
+        savepoint.commit()?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl<'conn, 'q> Drop for UndoSession<'conn, 'q> {
+    fn drop(&mut self) {
+        // Dropping a non-committed `Savepoint` rolls the connection back to it.
+        self.savepoint.take();
+
+        if !self.committed {
+            for request in self.consumed_requests.drain(..) {
+                self.queue.offer(request);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create::create_database;
+
+    fn sample_request(byte: u8) -> UnappliedRequest {
+        UnappliedRequest {
+            friend_public_key: vec![byte; 32],
+            currency: "FST".to_owned(),
+            request_id: vec![byte; 16],
+            generation: vec![byte; 8],
+        }
+    }
+
+    #[test]
+    fn test_rollback_requeues_consumed_request() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let mut queue = UnappliedQueue::new();
+
+        {
+            let mut session = UndoSession::new(&mut conn, &mut queue).unwrap();
+            session.mark_request_consumed(sample_request(1));
+            // Session is dropped here without calling push(), simulating an aborted operation.
+        }
+
+        assert_eq!(queue.pending().count(), 1);
+    }
+
+    #[test]
+    fn test_push_does_not_requeue() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_database(&mut conn).unwrap();
+        let mut queue = UnappliedQueue::new();
+
+        {
+            let mut session = UndoSession::new(&mut conn, &mut queue).unwrap();
+            session.mark_request_consumed(sample_request(2));
+            session.push().unwrap();
+        }
+
+        assert_eq!(queue.pending().count(), 0);
+    }
+}
@@ -0,0 +1,14 @@
+/// Why a `RequestSendFunds` was cancelled, carried along `CancelSendFundsOp`/`McCancel` so the
+/// reason survives the trip back to the request's originator instead of arriving as a bare
+/// cancellation with no explanation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// The queued fee (`left_fees`) did not meet the currency's current congestion-aware minimum.
+    InsufficientFee,
+    /// The channel's credit line does not have room for this request's amount.
+    InsufficientCapacity,
+    /// The request sat unresolved for longer than its allowed lifetime.
+    Expired,
+    /// A hop along the route reported an inconsistency or error unrelated to capacity or fees.
+    RemoteError,
+}
@@ -0,0 +1,54 @@
+use proto::funder::messages::{Currency, McBalance};
+
+// Note on scope: `FeePolicy` only ever prices a single request's `left_fees` against its own
+// currency's congestion (see `min_left_fees`/`queue_request`). It is not the fee-maximizing
+// *batch admission* scheduler requested separately (ranking which pending `RequestSendFundsOp`s
+// make it into a batch by `left_fees` when more are eligible than `max_operations_in_batch`
+// allows, while still always admitting backwards ops first and reserving a starvation-proof quota
+// for the oldest queued requests). That request's own targets - `collect_currencies_operations`,
+// `RouterDbClient`, `max_operations_in_batch` - do not exist anywhere in this snapshot, so there
+// is no batching pipeline here to plug a scheduler trait into; this remains an open gap rather
+// than something this file (or any other in this snapshot) closes.
+
+/// Decides the smallest `left_fees` we are willing to forward a request with for a given
+/// currency, given how congested that currency's credit line already is. Plugged into
+/// `OutMoveToken::queue_request` so the fee floor can be swapped out (for example, a flat
+/// minimum-fee policy for testing) without changing the queueing logic itself.
+pub trait FeePolicy {
+    /// `used_capacity` and `local_max_debt` are both in the same units as `McBalance::balance`.
+    fn min_left_fees(&self, currency: &Currency, local_max_debt: u128, used_capacity: u128) -> u128;
+}
+
+/// A congestion-aware fee policy: the required minimum fee rises linearly from `base_fee`, at
+/// zero congestion, up to `base_fee + max_congestion_fee` as `used_capacity` approaches
+/// `local_max_debt`. This prices scarce remaining credit rather than letting a channel that is
+/// nearly full be flooded with requests that all pay the bare minimum fee.
+pub struct LinearCongestionFeePolicy {
+    pub base_fee: u128,
+    pub max_congestion_fee: u128,
+}
+
+impl FeePolicy for LinearCongestionFeePolicy {
+    fn min_left_fees(&self, _currency: &Currency, local_max_debt: u128, used_capacity: u128) -> u128 {
+        if local_max_debt == 0 {
+            return self.base_fee.saturating_add(self.max_congestion_fee);
+        }
+        let used_capacity = used_capacity.min(local_max_debt);
+        let congestion_fee = self
+            .max_congestion_fee
+            .saturating_mul(used_capacity)
+            / local_max_debt;
+        self.base_fee.saturating_add(congestion_fee)
+    }
+}
+
+/// The amount of `local_max_debt` already committed by settled balance and in-flight requests in
+/// this currency - the quantity a congestion-aware [`FeePolicy`] prices against.
+pub fn used_local_capacity(mc_balance: &McBalance) -> u128 {
+    let committed_balance = if mc_balance.balance < 0 {
+        (-mc_balance.balance) as u128
+    } else {
+        0
+    };
+    committed_balance.saturating_add(mc_balance.local_pending_debt)
+}
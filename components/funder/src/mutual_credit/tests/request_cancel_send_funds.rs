@@ -16,6 +16,7 @@ use proto::funder::messages::{
     CancelSendFundsOp, Currency, FriendTcOp, FriendsRoute, RequestSendFundsOp,
 };
 
+use crate::mutual_credit::cancel_reason::CancelReason;
 use crate::mutual_credit::tests::utils::{mc_server, MutualCredit};
 use crate::mutual_credit::types::McTransaction;
 
@@ -87,7 +88,10 @@ async fn task_request_cancel_send_funds(test_executor: TestExecutor) {
 
     // -----[CancelSendFunds]--------
     // ------------------------------
-    let cancel_send_funds = CancelSendFundsOp { request_id };
+    let cancel_send_funds = CancelSendFundsOp {
+        request_id,
+        reason: CancelReason::RemoteError,
+    };
 
     process_operations_list(
         &mut mc_transaction,
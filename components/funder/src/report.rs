@@ -1,13 +1,15 @@
 use im::hashmap::HashMap as ImHashMap;
 
 use crypto::identity::{PublicKey, Signature};
+use proto::crypto::{HashResult, Uid};
+use proto::funder::messages::{Currency, FriendsRoute, RequestSendFundsOp};
 use utils::int_convert::usize_to_u64;
 
 use crate::friend::{FriendState, ChannelStatus, FriendMutation};
 use crate::state::{FunderState, FunderMutation};
 use crate::types::{RequestsStatus, FriendStatus, AddFriend, FriendMoveToken};
-use crate::mutual_credit::types::{McBalance, McRequestsStatus};
-use crate::token_channel::{TokenChannel, TcDirection, TcMutation}; 
+use crate::mutual_credit::types::{McBalance, McRequestsStatus, McState};
+use crate::token_channel::{TokenChannel, TcDirection, TcMutation};
 use crate::liveness::Liveness;
 
 #[derive(Clone, Debug)]
@@ -22,24 +24,84 @@ pub enum FriendLivenessReport {
     Offline,
 }
 
+/// A single in-flight request, as exposed to report consumers. Carries enough of
+/// `RequestSendFundsOp` for an app to show the user something meaningful (route, amounts),
+/// without the cryptographic commitment fields (`src_hashed_lock`, `hmac`) that are only
+/// meaningful to the protocol layer.
 #[derive(Clone, Debug)]
-pub struct TcReport {
-    pub direction: DirectionReport,
+pub struct PendingRequestReport {
+    pub request_id: Uid,
+    pub route: FriendsRoute,
+    pub dest_payment: u128,
+    pub total_dest_payment: u128,
+    pub left_fees: u128,
+    pub invoice_hash: HashResult,
+}
+
+impl From<&RequestSendFundsOp> for PendingRequestReport {
+    fn from(request: &RequestSendFundsOp) -> Self {
+        PendingRequestReport {
+            request_id: request.request_id.clone(),
+            route: request.route.clone(),
+            dest_payment: request.dest_payment,
+            total_dest_payment: request.total_dest_payment,
+            left_fees: request.left_fees,
+            invoice_hash: request.invoice_hash.clone(),
+        }
+    }
+}
+
+/// The report for a single currency within a consistent token channel. A token channel may carry
+/// several currencies at once, each with its own independent balance and pending requests.
+#[derive(Clone, Debug)]
+pub struct TcCurrencyReport {
     pub balance: McBalance,
     pub requests_status: McRequestsStatus,
-    pub num_local_pending_requests: u64,
-    pub num_remote_pending_requests: u64,
+    /// Requests we sent to the remote side, awaiting its response.
+    pub local_pending_requests: Vec<PendingRequestReport>,
+    /// Requests the remote side sent to us (to forward onwards), awaiting our response.
+    pub remote_pending_requests: Vec<PendingRequestReport>,
+    /// The maximum debt we currently allow the remote side to accrue towards us.
+    pub remote_max_debt: u128,
+    /// How much more could still be routed *through* this friend towards the remote side
+    /// before `remote_max_debt` is reached, accounting for debt already committed by pending
+    /// requests. Used by route planning to avoid picking a channel that is already saturated.
+    pub available_send_capacity: u128,
+    /// How much more this friend could still route back to us before our own extended credit
+    /// runs out.
+    pub available_receive_capacity: u128,
+}
+
+/// `balance.balance` is signed from the local node's point of view: positive means the remote
+/// owes us, negative means we owe the remote. `remote_max_debt` is the line of credit we've
+/// extended in this currency; `remote_pending_debt`/`local_pending_debt` are amounts already
+/// committed by in-flight requests that haven't settled yet, so they count against that line
+/// just as much as settled balance does.
+fn compute_capacities(balance: &McBalance, remote_max_debt: u128) -> (u128, u128) {
+    let committed_towards_remote = (-balance.balance).max(0) as u128 + balance.local_pending_debt;
+    let available_send_capacity = remote_max_debt.saturating_sub(committed_towards_remote);
+
+    let committed_from_remote = balance.balance.max(0) as u128 + balance.remote_pending_debt;
+    let available_receive_capacity = remote_max_debt.saturating_sub(committed_from_remote);
+
+    (available_send_capacity, available_receive_capacity)
+}
+
+#[derive(Clone, Debug)]
+pub struct TcReport {
+    pub direction: DirectionReport,
+    pub currencies: ImHashMap<Currency, TcCurrencyReport>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ResetTermsReport {
-    reset_token: Signature,
-    balance_for_reset: i128,
+    pub reset_token: Signature,
+    pub balances_for_reset: ImHashMap<Currency, i128>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ChannelInconsistentReport {
-    pub local_reset_terms_balance: i128,
+    pub local_reset_terms_balances: ImHashMap<Currency, i128>,
     pub opt_remote_reset_terms: Option<ResetTermsReport>,
 }
 
@@ -91,6 +153,7 @@ pub enum FriendReportMutation<A> {
     SetNumPendingRequests(u64),
     SetFriendStatus(FriendStatus),
     SetNumPendingUserRequests(u64),
+    SetLiveness(FriendLivenessReport),
 }
 
 #[derive(Clone, Debug)]
@@ -100,6 +163,9 @@ pub struct AddFriendReport<A> {
     pub name: String,
     pub balance: i128, // Initial balance
     pub channel_status: ChannelStatusReport,
+    pub wanted_remote_max_debt: u128,
+    pub wanted_local_requests_status: RequestsStatus,
+    pub status: FriendStatus,
 }
 
 #[allow(unused)]
@@ -111,18 +177,45 @@ pub enum FunderReportMutation<A> {
     SetNumReadyReceipts(u64),
 }
 
+fn create_tc_currency_report(mutual_credit_state: &McState) -> TcCurrencyReport {
+    let (available_send_capacity, available_receive_capacity) =
+        compute_capacities(&mutual_credit_state.balance, mutual_credit_state.remote_max_debt);
+
+    TcCurrencyReport {
+        balance: mutual_credit_state.balance.clone(),
+        requests_status: mutual_credit_state.requests_status.clone(),
+        local_pending_requests: mutual_credit_state
+            .pending_requests
+            .pending_local_requests
+            .values()
+            .map(PendingRequestReport::from)
+            .collect(),
+        remote_pending_requests: mutual_credit_state
+            .pending_requests
+            .pending_remote_requests
+            .values()
+            .map(PendingRequestReport::from)
+            .collect(),
+        remote_max_debt: mutual_credit_state.remote_max_debt,
+        available_send_capacity,
+        available_receive_capacity,
+    }
+}
+
 fn create_token_channel_report(token_channel: &TokenChannel) -> TcReport {
     let direction = match token_channel.get_direction() {
         TcDirection::Incoming(_) => DirectionReport::Incoming,
         TcDirection::Outgoing(_) => DirectionReport::Outgoing,
     };
-    let mutual_credit_state = token_channel.get_mutual_credit().state();
+
+    let mut currencies = ImHashMap::new();
+    for (currency, mutual_credit) in token_channel.get_currency_mutual_credits() {
+        currencies.insert(currency.clone(), create_tc_currency_report(&mutual_credit.state()));
+    }
+
     TcReport {
         direction,
-        balance: mutual_credit_state.balance.clone(),
-        requests_status: mutual_credit_state.requests_status.clone(),
-        num_local_pending_requests: usize_to_u64(mutual_credit_state.pending_requests.pending_local_requests.len()).unwrap(),
-        num_remote_pending_requests: usize_to_u64(mutual_credit_state.pending_requests.pending_remote_requests.len()).unwrap(),
+        currencies,
     }
 }
 
@@ -134,11 +227,11 @@ fn create_channel_status_report<A: Clone>(channel_status: &ChannelStatus) -> Cha
                 .map(|remote_reset_terms|
                     ResetTermsReport {
                         reset_token: remote_reset_terms.reset_token.clone(),
-                        balance_for_reset: remote_reset_terms.balance_for_reset,
+                        balances_for_reset: remote_reset_terms.balances_for_reset.clone(),
                     }
                 );
             let channel_inconsistent_report = ChannelInconsistentReport {
-                local_reset_terms_balance: channel_inconsistent.local_reset_terms.balance_for_reset,
+                local_reset_terms_balances: channel_inconsistent.local_reset_terms.balances_for_reset.clone(),
                 opt_remote_reset_terms,
             };
             ChannelStatusReport::Inconsistent(channel_inconsistent_report)
@@ -186,7 +279,22 @@ pub fn create_report<A: Clone>(funder_state: &FunderState<A>, liveness: &Livenes
 
 }
 
-// TODO: How to add liveness mutation?
+/// Build a FunderReportMutation for a friend's liveness transition. Unlike the other
+/// FunderReportMutation variants, this one isn't derived from a FunderMutation: liveness is
+/// tracked by `Liveness` independently of `FunderState`, so it gets its own constructor instead
+/// of a `create_*_report_mutation` match arm.
+pub fn create_friend_liveness_mutation<A>(friend_public_key: &PublicKey, is_online: bool) -> FunderReportMutation<A> {
+    let liveness_report = if is_online {
+        FriendLivenessReport::Online
+    } else {
+        FriendLivenessReport::Offline
+    };
+    FunderReportMutation::FriendReportMutation((
+        friend_public_key.clone(),
+        FriendReportMutation::SetLiveness(liveness_report),
+    ))
+}
+
 pub fn create_friend_report_mutation<A: Clone + 'static>(friend_mutation: &FriendMutation<A>,
                                            friend: &FriendState<A>) -> Option<FriendReportMutation<A>> {
 
@@ -266,6 +374,9 @@ pub fn create_funder_report_mutation<A: Clone + 'static>(funder_mutation: &Funde
                 name: add_friend.name.clone(),
                 balance: add_friend.balance.clone(), // Initial balance
                 channel_status: create_channel_status_report::<A>(&friend_after.channel_status),
+                wanted_remote_max_debt: friend_after.wanted_remote_max_debt,
+                wanted_local_requests_status: friend_after.wanted_local_requests_status.clone(),
+                status: friend_after.status.clone(),
             };
             Some(FunderReportMutation::AddFriend(add_friend_report))
         },
@@ -290,17 +401,69 @@ pub fn create_funder_report_mutation<A: Clone + 'static>(funder_mutation: &Funde
 }
 
 
+impl<A: Clone> FriendReport<A> {
+    fn mutate(&mut self, mutation: &FriendReportMutation<A>) {
+        match mutation {
+            FriendReportMutation::SetFriendInfo((address, name)) => {
+                self.address = address.clone();
+                self.name = name.clone();
+            },
+            FriendReportMutation::SetChannelStatus(channel_status_report) => {
+                self.channel_status = channel_status_report.clone();
+            },
+            FriendReportMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
+                self.wanted_remote_max_debt = *wanted_remote_max_debt;
+            },
+            FriendReportMutation::SetWantedLocalRequestsStatus(requests_status) => {
+                self.wanted_local_requests_status = requests_status.clone();
+            },
+            FriendReportMutation::SetNumPendingResponses(num_pending_responses) => {
+                self.num_pending_responses = *num_pending_responses;
+            },
+            FriendReportMutation::SetNumPendingRequests(num_pending_requests) => {
+                self.num_pending_requests = *num_pending_requests;
+            },
+            FriendReportMutation::SetFriendStatus(status) => {
+                self.status = status.clone();
+            },
+            FriendReportMutation::SetNumPendingUserRequests(num_pending_user_requests) => {
+                self.num_pending_user_requests = *num_pending_user_requests;
+            },
+            FriendReportMutation::SetLiveness(liveness) => {
+                self.liveness = liveness.clone();
+            },
+        }
+    }
+}
+
 impl<A: Clone> FunderReport<A> {
     fn mutate(&mut self, mutation: &FunderReportMutation<A>) {
         match mutation {
             FunderReportMutation::AddFriend(add_friend_report) => {
-                // TODO: AddFriend Should include information about how to build channel_status
-                unimplemented!();
+                let friend_report = FriendReport {
+                    public_key: add_friend_report.friend_public_key.clone(),
+                    address: add_friend_report.address.clone(),
+                    name: add_friend_report.name.clone(),
+                    opt_last_incoming_move_token: None,
+                    liveness: FriendLivenessReport::Offline,
+                    channel_status: add_friend_report.channel_status.clone(),
+                    wanted_remote_max_debt: add_friend_report.wanted_remote_max_debt,
+                    wanted_local_requests_status: add_friend_report.wanted_local_requests_status.clone(),
+                    num_pending_responses: 0,
+                    num_pending_requests: 0,
+                    status: add_friend_report.status.clone(),
+                    num_pending_user_requests: 0,
+                };
+                let _ = self.friends.insert(add_friend_report.friend_public_key.clone(), friend_report);
             },
             FunderReportMutation::RemoveFriend(friend_public_key) => {
                 let _ = self.friends.remove(&friend_public_key);
             },
-            FunderReportMutation::FriendReportMutation((friend_public_key, friend_report_mutation)) => unimplemented!(),
+            FunderReportMutation::FriendReportMutation((friend_public_key, friend_report_mutation)) => {
+                if let Some(friend_report) = self.friends.get_mut(friend_public_key) {
+                    friend_report.mutate(friend_report_mutation);
+                }
+            },
             FunderReportMutation::SetNumReadyReceipts(num_ready_receipts) => {
                 self.num_ready_receipts = *num_ready_receipts;
             },
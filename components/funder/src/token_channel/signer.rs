@@ -0,0 +1,29 @@
+use common::conn::BoxFuture;
+
+use identity::IdentityClient;
+
+use proto::crypto::Signature;
+
+/// A signing request could not be completed.
+///
+/// Kept separate from `TokenChannelError::RequestSignatureError` so that callers plugging in a
+/// [`TokenSigner`] of their own (for example a hardware wallet or a remote signing service) are
+/// not forced to fabricate an `IdentityClient`-flavoured error; `TokenChannelError` still wraps
+/// this at every call site, same as it already did for `IdentityClient` failures.
+#[derive(Debug)]
+pub struct SignerError;
+
+/// Anything able to produce a signature over a reset token or a move token buffer.
+///
+/// `TokenChannel` only ever needs "sign this buffer", so it is written against this trait rather
+/// than the concrete `IdentityClient`, letting a hardware-wallet or remote-signer implementation
+/// stand in for the default in-process identity service.
+pub trait TokenSigner {
+    fn request_signature(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<Signature, SignerError>>;
+}
+
+impl TokenSigner for IdentityClient {
+    fn request_signature(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<Signature, SignerError>> {
+        Box::pin(async move { IdentityClient::request_signature(self, message).await.map_err(|_| SignerError) })
+    }
+}
@@ -12,9 +12,9 @@ use common::async_rpc::OpError;
 use common::conn::BoxFuture;
 
 use crypto::hash::{hash_buffer, Hasher};
-use crypto::identity::compare_public_key;
+use crypto::identity::{compare_public_key, verify_signature};
 
-use identity::IdentityClient;
+use crate::token_channel::signer::TokenSigner;
 
 use proto::app_server::messages::RelayAddress;
 use proto::crypto::{HashResult, PublicKey, RandValue, Signature};
@@ -31,6 +31,8 @@ use signature::verify::verify_move_token;
 
 use database::transaction::{TransFunc, Transaction};
 
+use crate::mutual_credit::cancel_reason::CancelReason;
+use crate::mutual_credit::fee_policy::{used_local_capacity, FeePolicy};
 use crate::mutual_credit::incoming::{process_operation, IncomingMessage, ProcessOperationError};
 use crate::mutual_credit::outgoing::{
     queue_cancel, queue_request, queue_response, QueueOperationError,
@@ -51,6 +53,10 @@ pub enum TokenChannelError {
     InvalidState,
     OpError(OpError),
     QueueOperationError(QueueOperationError),
+    /// A counterparty's reset terms were anchored at a `move_token_counter` we have already moved
+    /// past, meaning the counterparty has fallen behind (or is replaying an old message) and its
+    /// terms no longer reflect the real channel state.
+    StaleCounterparty,
 }
 
 #[derive(Debug)]
@@ -58,6 +64,21 @@ pub struct MoveTokenReceived {
     pub incoming_messages: Vec<(Currency, IncomingMessage)>,
 }
 
+/// A remote key rotation proposal was rejected. Unlike `TokenChannelError`, this is never a
+/// reason to move the token channel to `Inconsistent`: a rejected rotation simply leaves the
+/// channel using whichever public key it already trusted.
+#[derive(Debug)]
+pub enum KeyRotationError {
+    /// `old_public_key` in the proposal does not match the remote public key we currently trust.
+    UnknownOldPublicKey,
+    /// The proposal's signature does not verify against `old_public_key`.
+    InvalidSignature,
+    /// `move_token_counter` does not match the channel's current counter, so the proposal is
+    /// either stale or was not issued at a point we can safely apply it.
+    StaleProposal,
+    OpError(OpError),
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum ReceiveMoveTokenOutput {
@@ -67,6 +88,42 @@ pub enum ReceiveMoveTokenOutput {
     ChainInconsistent(ResetTerms),
 }
 
+/// What to send to the remote side right after reconnecting, to reestablish the channel without
+/// assuming either side still has whatever was in flight when the connection dropped.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ReestablishAction {
+    /// We owe the remote side our last move token; it may never have arrived, so resend it.
+    ResendMoveToken(MoveToken),
+    /// We are waiting for the remote side's move token; ask it to resend, in case its last one
+    /// was lost along with the old connection.
+    RequestMoveToken,
+    /// The channel was already inconsistent before the reconnect; resend our reset terms.
+    ResendResetTerms(ResetTerms),
+}
+
+/// Figure out what to (re)send to the remote side immediately after reconnecting, based on
+/// whichever state the channel was left in by the dropped connection. Performs no mutation: a
+/// reconnect never changes the token channel's state on its own, it only determines what was
+/// possibly lost in transit and needs to be resent.
+pub async fn reestablish_channel(
+    tc_client: &mut impl TcDbClient,
+) -> Result<ReestablishAction, TokenChannelError> {
+    Ok(match tc_client.get_tc_status().await? {
+        TcStatus::ConsistentOut(move_token_out, _opt_move_token_in) => {
+            ReestablishAction::ResendMoveToken(move_token_out)
+        }
+        TcStatus::ConsistentIn(..) => ReestablishAction::RequestMoveToken,
+        TcStatus::Inconsistent(local_reset_terms, _opt_remote_reset_terms) => {
+            ReestablishAction::ResendResetTerms(ResetTerms {
+                reset_token: local_reset_terms.reset_token,
+                move_token_counter: local_reset_terms.move_token_counter,
+                reset_balances: local_balances_for_reset(tc_client).await?,
+            })
+        }
+    })
+}
+
 /// Create a token from a public key
 /// Currently this function puts the public key in the beginning of the signature buffer,
 /// as the public key is shorter than a signature.
@@ -235,7 +292,7 @@ where
 
 pub async fn handle_in_move_token<C>(
     tc_client: &mut C,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     new_move_token: MoveToken,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
@@ -256,11 +313,12 @@ where
             )
             .await
         }
-        TcStatus::ConsistentOut(move_token_out, _opt_move_token_in) => {
+        TcStatus::ConsistentOut(move_token_out, opt_move_token_in) => {
             handle_in_move_token_dir_out(
                 tc_client,
                 identity_client,
                 move_token_out,
+                opt_move_token_in,
                 new_move_token,
                 local_public_key,
                 remote_public_key,
@@ -341,7 +399,7 @@ where
 
 /// Generate a reset token, to be used by remote side if he wants to accept the reset terms.
 async fn create_reset_token(
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
     move_token_counter: u128,
@@ -354,11 +412,61 @@ async fn create_reset_token(
         .map_err(|_| TokenChannelError::RequestSignatureError)?)
 }
 
-/// Set token channel to be inconsistent
-/// Local reset terms are automatically calculated
+/// Set token channel to be inconsistent, computing local reset terms automatically.
+///
+/// Idempotent: if the channel is already `Inconsistent` (for example because a crash interrupted
+/// us right after the previous call and this code path is being replayed during recovery), the
+/// existing local reset terms are returned unchanged instead of generating a new reset token -
+/// otherwise a crash-and-retry could invalidate a reset token the remote side already received
+/// and is waiting to act on. To deliberately move the proposal forward (e.g. because the remote
+/// side appears to be stuck), use `re_propose_reset_terms` instead.
 async fn set_inconsistent(
     tc_client: &mut impl TcDbClient,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
+    local_public_key: &PublicKey,
+    remote_public_key: &PublicKey,
+) -> Result<(Signature, u128), TokenChannelError> {
+    if let TcStatus::Inconsistent(local_reset_terms, _opt_remote_reset_terms) =
+        tc_client.get_tc_status().await?
+    {
+        return Ok((
+            local_reset_terms.reset_token,
+            local_reset_terms.move_token_counter,
+        ));
+    }
+
+    propose_reset_terms(
+        tc_client,
+        identity_client,
+        local_public_key,
+        remote_public_key,
+    )
+    .await
+}
+
+/// Monotonically re-propose local reset terms for a channel that is already `Inconsistent`,
+/// producing a fresh reset token with a strictly greater `move_token_counter` than the one
+/// currently on file. Unlike `set_inconsistent`, this is deliberately not idempotent: each call
+/// advances the proposal, which is useful when the remote side appears to have lost or ignored
+/// the one we sent it earlier.
+pub async fn re_propose_reset_terms(
+    tc_client: &mut impl TcDbClient,
+    identity_client: &mut impl TokenSigner,
+    local_public_key: &PublicKey,
+    remote_public_key: &PublicKey,
+) -> Result<(Signature, u128), TokenChannelError> {
+    propose_reset_terms(
+        tc_client,
+        identity_client,
+        local_public_key,
+        remote_public_key,
+    )
+    .await
+}
+
+async fn propose_reset_terms(
+    tc_client: &mut impl TcDbClient,
+    identity_client: &mut impl TokenSigner,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
 ) -> Result<(Signature, u128), TokenChannelError> {
@@ -391,9 +499,94 @@ async fn set_inconsistent(
     Ok((local_reset_token, local_reset_move_token_counter))
 }
 
+/// Domain-separates identity-key-rotation signatures from reset-token and move-token signatures,
+/// so that a signature produced for one purpose can never be replayed as another.
+const KEY_ROTATION_PREFIX: &[u8] = b"FUNDER_KEY_ROTATION";
+
+fn key_rotation_signature_buff(
+    old_public_key: &PublicKey,
+    new_public_key: &PublicKey,
+    move_token_counter: u128,
+) -> Vec<u8> {
+    let mut buff = Vec::new();
+    buff.extend_from_slice(KEY_ROTATION_PREFIX);
+    buff.extend_from_slice(old_public_key);
+    buff.extend_from_slice(new_public_key);
+    buff.extend_from_slice(&move_token_counter.to_be_bytes());
+    buff
+}
+
+/// A signed proposal to rotate the local identity's public key used for this token channel,
+/// without forcing a reset. Once the remote side accepts it, subsequent move tokens are signed
+/// and verified against `new_public_key` instead of `old_public_key`.
+#[derive(Debug, Clone)]
+pub struct KeyRotation {
+    pub old_public_key: PublicKey,
+    pub new_public_key: PublicKey,
+    pub move_token_counter: u128,
+    pub signature: Signature,
+}
+
+/// Sign a proposal to rotate our local identity key for this channel. `move_token_counter` should
+/// be the channel's current move token counter, tying the rotation to a specific point in the
+/// channel's history so it can't be replayed out of order.
+pub async fn create_key_rotation(
+    identity_client: &mut impl TokenSigner,
+    old_public_key: &PublicKey,
+    new_public_key: &PublicKey,
+    move_token_counter: u128,
+) -> Result<KeyRotation, TokenChannelError> {
+    let sign_buffer = key_rotation_signature_buff(old_public_key, new_public_key, move_token_counter);
+    let signature = identity_client
+        .request_signature(sign_buffer)
+        .await
+        .map_err(|_| TokenChannelError::RequestSignatureError)?;
+
+    Ok(KeyRotation {
+        old_public_key: old_public_key.clone(),
+        new_public_key: new_public_key.clone(),
+        move_token_counter,
+        signature,
+    })
+}
+
+/// Verify and apply a remote side's key rotation proposal against the channel's current state.
+/// Returns the rotated public key on success, to be persisted by the caller as the new remote
+/// public key for this friend. A proposal that fails verification is just rejected: unlike a
+/// mismatched move token, it never pushes the channel into `Inconsistent`, since no balances or
+/// pending operations are affected by it either way.
+pub async fn verify_remote_key_rotation(
+    tc_client: &mut impl TcDbClient,
+    key_rotation: &KeyRotation,
+    expected_remote_public_key: &PublicKey,
+) -> Result<PublicKey, KeyRotationError> {
+    if &key_rotation.old_public_key != expected_remote_public_key {
+        return Err(KeyRotationError::UnknownOldPublicKey);
+    }
+
+    let current_move_token_counter = tc_client
+        .get_move_token_counter()
+        .await
+        .map_err(KeyRotationError::OpError)?;
+    if key_rotation.move_token_counter != current_move_token_counter {
+        return Err(KeyRotationError::StaleProposal);
+    }
+
+    let sign_buffer = key_rotation_signature_buff(
+        &key_rotation.old_public_key,
+        &key_rotation.new_public_key,
+        key_rotation.move_token_counter,
+    );
+    if !verify_signature(&sign_buffer, &key_rotation.old_public_key, &key_rotation.signature) {
+        return Err(KeyRotationError::InvalidSignature);
+    }
+
+    Ok(key_rotation.new_public_key.clone())
+}
+
 async fn handle_in_move_token_dir_in(
     tc_client: &mut impl TcDbClient,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
     move_token_in: MoveTokenHashed,
@@ -470,8 +663,9 @@ where
 
 async fn handle_in_move_token_dir_out<C>(
     tc_client: &mut C,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     move_token_out: MoveToken,
+    opt_move_token_in: Option<MoveTokenHashed>,
     new_move_token: MoveToken,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
@@ -518,6 +712,15 @@ where
         Ok(ReceiveMoveTokenOutput::RetransmitOutgoing(
             move_token_out.clone(),
         ))
+    } else if is_common_ancestor_resend(&opt_move_token_in, &new_move_token) {
+        // The remote side's chain does not extend our current outgoing move token, but it does
+        // extend the move token we last received from it (our shared common ancestor) - the
+        // remote most likely lost its state after acking that message and never saw the move
+        // token we sent afterwards. Rather than treat this as a real divergence, just resend our
+        // current outgoing move token, exactly as we would for a plain retransmit.
+        Ok(ReceiveMoveTokenOutput::RetransmitOutgoing(
+            move_token_out.clone(),
+        ))
     } else {
         // Inconsistency
         let (local_reset_token, local_reset_move_token_counter) = set_inconsistent(
@@ -535,26 +738,166 @@ where
     }
 }
 
-// TODO: Should we move this function to offset-signature crate?
-async fn hash_mc_infos(
-    mut mc_infos: impl Stream<Item = Result<(Currency, McBalance), OpError>> + Unpin,
-) -> Result<HashResult, OpError> {
+/// Does `new_move_token` extend the last move token we received from the remote side (our common
+/// ancestor with it), rather than our current outgoing move token? If so, the two chains have not
+/// really diverged: the remote is simply resending from a point both sides already agree on.
+fn is_common_ancestor_resend(
+    opt_move_token_in: &Option<MoveTokenHashed>,
+    new_move_token: &MoveToken,
+) -> bool {
+    match opt_move_token_in {
+        Some(move_token_in) => new_move_token.old_token == move_token_in.new_token,
+        None => false,
+    }
+}
+
+fn merkle_leaf_hash(currency: &Currency, mc_balance: &McBalance) -> HashResult {
     let mut hasher = Hasher::new();
-    // Last seen currency, used to verify sorted order:
+    hasher.update(&hash_buffer(&currency.canonical_serialize()));
+    hasher.update(&mc_balance.canonical_serialize());
+    hasher.finalize()
+}
+
+/// Combine one level of a Merkle tree into the level above it. An odd node out at the end of a
+/// level is promoted unchanged rather than paired with itself, avoiding the well known
+/// duplicate-leaf second-preimage issue some naive Merkle tree constructions have.
+fn merkle_parent_level(level: &[HashResult]) -> Vec<HashResult> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => {
+                let mut hasher = Hasher::new();
+                hasher.update(left);
+                hasher.update(right);
+                hasher.finalize()
+            }
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Every level of a balances Merkle tree, from the leaves (level 0) up to the root (the last
+/// level's single element). Kept around as a whole (rather than just the root) so that a proof
+/// for any one leaf can be derived from it without rebuilding the tree.
+fn merkle_levels(leaves: Vec<HashResult>) -> Vec<Vec<HashResult>> {
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let next_level = merkle_parent_level(levels.last().unwrap());
+        levels.push(next_level);
+    }
+    levels
+}
+
+/// One step of a Merkle inclusion proof: the sibling hash at a tree level, and which side of the
+/// pair it sits on.
+#[derive(Debug, Clone)]
+pub struct MerkleProofStep {
+    pub sibling: HashResult,
+    pub sibling_is_left: bool,
+}
+
+/// Proof that a single currency's balance was included in a `TokenInfo::balances_hash` Merkle
+/// commitment, without needing the full list of every currency's balance.
+#[derive(Debug, Clone)]
+pub struct BalanceInclusionProof {
+    pub leaf: HashResult,
+    pub steps: Vec<MerkleProofStep>,
+}
+
+fn balance_inclusion_proof_from_levels(
+    levels: &[Vec<HashResult>],
+    mut index: usize,
+) -> BalanceInclusionProof {
+    let leaf = levels[0][index].clone();
+    let mut steps = Vec::new();
+    for level in &levels[..levels.len() - 1] {
+        if index % 2 == 0 {
+            // We are the left child; a sibling to our right exists unless we were the odd node
+            // out and got promoted unchanged.
+            if let Some(sibling) = level.get(index + 1) {
+                steps.push(MerkleProofStep {
+                    sibling: sibling.clone(),
+                    sibling_is_left: false,
+                });
+            }
+        } else {
+            steps.push(MerkleProofStep {
+                sibling: level[index - 1].clone(),
+                sibling_is_left: true,
+            });
+        }
+        index /= 2;
+    }
+    BalanceInclusionProof { leaf, steps }
+}
+
+/// Verify a Merkle inclusion proof against a `balances_hash` root produced by `hash_mc_infos`.
+pub fn verify_balance_inclusion_proof(proof: &BalanceInclusionProof, root: &HashResult) -> bool {
+    let mut current = proof.leaf.clone();
+    for step in &proof.steps {
+        let mut hasher = Hasher::new();
+        if step.sibling_is_left {
+            hasher.update(&step.sibling);
+            hasher.update(&current);
+        } else {
+            hasher.update(&current);
+            hasher.update(&step.sibling);
+        }
+        current = hasher.finalize();
+    }
+    &current == root
+}
+
+/// Collect a sorted-by-currency balances stream into Merkle leaves, asserting along the way that
+/// the stream really is sorted (Required so that two independent readers of the same balances
+/// always build the same tree, and so a currency's position in the tree is stable).
+async fn collect_balance_leaves(
+    mut mc_infos: impl Stream<Item = Result<(Currency, McBalance), OpError>> + Unpin,
+) -> Result<Vec<(Currency, HashResult)>, OpError> {
+    let mut leaves = Vec::new();
     let mut opt_last_currency: Option<Currency> = None;
 
     while let Some(item) = mc_infos.next().await {
         let (currency, mc_balance) = item?;
         // Ensure that the list is sorted:
-        if let Some(last_currency) = opt_last_currency {
-            assert!(last_currency <= currency);
+        if let Some(last_currency) = &opt_last_currency {
+            assert!(last_currency <= &currency);
         }
-        // Update the last currency:
         opt_last_currency = Some(currency.clone());
-        hasher.update(&hash_buffer(&currency.canonical_serialize()));
-        hasher.update(&mc_balance.canonical_serialize());
+        leaves.push((currency.clone(), merkle_leaf_hash(&currency, &mc_balance)));
     }
-    Ok(hasher.finalize())
+    Ok(leaves)
+}
+
+// TODO: Should we move this function to offset-signature crate?
+async fn hash_mc_infos(
+    mc_infos: impl Stream<Item = Result<(Currency, McBalance), OpError>> + Unpin,
+) -> Result<HashResult, OpError> {
+    let leaves = collect_balance_leaves(mc_infos).await?;
+    let levels = merkle_levels(leaves.into_iter().map(|(_currency, leaf)| leaf).collect());
+    Ok(levels.last().unwrap()[0].clone())
+}
+
+/// Compute both the balances Merkle root and an inclusion proof for `target_currency`'s balance
+/// within it, in one pass over `mc_infos`. Returns `None` if `target_currency` isn't present.
+pub async fn balance_inclusion_proof(
+    mc_infos: impl Stream<Item = Result<(Currency, McBalance), OpError>> + Unpin,
+    target_currency: &Currency,
+) -> Result<Option<(HashResult, BalanceInclusionProof)>, OpError> {
+    let leaves = collect_balance_leaves(mc_infos).await?;
+    let opt_index = leaves
+        .iter()
+        .position(|(currency, _leaf)| currency == target_currency);
+    let index = match opt_index {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+
+    let levels = merkle_levels(leaves.into_iter().map(|(_currency, leaf)| leaf).collect());
+    let root = levels.last().unwrap()[0].clone();
+    let proof = balance_inclusion_proof_from_levels(&levels, index);
+    Ok(Some((root, proof)))
 }
 
 #[derive(Debug)]
@@ -788,6 +1131,7 @@ async fn tc_op_from_incoming_friend_tc_op(
                 currency,
                 mc_op: McOp::Cancel(McCancel {
                     request_id: cancel_send_funds.request_id,
+                    reason: cancel_send_funds.reason,
                 }),
             }
         }
@@ -813,6 +1157,7 @@ fn friend_tc_op_from_outgoing_tc_op(tc_op: TcOp) -> FriendTcOp {
         }),
         McOp::Cancel(mc_cancel) => FriendTcOp::CancelSendFunds(CancelSendFundsOp {
             request_id: mc_cancel.request_id,
+            reason: mc_cancel.reason,
         }),
     }
 }
@@ -839,6 +1184,7 @@ where
         currency: Currency,
         mc_request: McRequest,
         local_max_debt: u128,
+        fee_policy: &impl FeePolicy,
     ) -> Result<Result<(), McCancel>, TokenChannelError> {
         // TODO: Remember to clean up all relevant pending requests when a currency is removed.
         // Otherwise we might get to this state:
@@ -852,6 +1198,18 @@ where
         // Note that the above statement might fail, and we might need to handle it.
         todo!();
 
+        // Reject the request up front if it doesn't pay enough to cover this currency's current
+        // congestion, rather than forwarding it only to have it get stuck behind better-paying
+        // requests:
+        let used_capacity = used_local_capacity(&mc_client.get_mc_balance().await?);
+        let min_left_fees = fee_policy.min_left_fees(&currency, local_max_debt, used_capacity);
+        if mc_request.left_fees < min_left_fees {
+            return Ok(Err(McCancel {
+                request_id: mc_request.request_id.clone(),
+                reason: CancelReason::InsufficientFee,
+            }));
+        }
+
         // queue_request might fail due to `local_max_debt`.
         let res = queue_request(mc_client, mc_request.clone(), &currency, local_max_debt).await?;
 
@@ -926,7 +1284,7 @@ where
 
     pub async fn finalize(
         self,
-        identity_client: &mut IdentityClient,
+        identity_client: &mut impl TokenSigner,
         local_public_key: &PublicKey,
         remote_public_key: &PublicKey,
     ) -> Result<MoveToken, TokenChannelError> {
@@ -1145,7 +1503,7 @@ pub async fn handle_out_move_token(
 /// reset terms.
 pub async fn accept_remote_reset(
     tc_client: &mut impl TcDbClient,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     operations: Vec<FriendTcOp>,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
@@ -1210,11 +1568,20 @@ pub async fn accept_remote_reset(
 /// Optionally returns local reset terms (If not already inconsistent)
 pub async fn load_remote_reset_terms(
     tc_client: &mut impl TcDbClient,
-    identity_client: &mut IdentityClient,
+    identity_client: &mut impl TokenSigner,
     remote_reset_terms: ResetTerms,
     local_public_key: &PublicKey,
     remote_public_key: &PublicKey,
 ) -> Result<Option<ResetTerms>, TokenChannelError> {
+    // Reject a stale counterparty: a reset proposal anchored at a move_token_counter we have
+    // already moved past does not reflect the real channel state. Accepting it anyway would let a
+    // counterparty that lost its state (or replayed an old message) roll us back, so check this
+    // before making any changes of our own.
+    let current_move_token_counter = tc_client.get_move_token_counter().await?;
+    if remote_reset_terms.move_token_counter < current_move_token_counter {
+        return Err(TokenChannelError::StaleCounterparty);
+    }
+
     // Check our current state:
     let ret_val = match tc_client.get_tc_status().await? {
         TcStatus::ConsistentIn(..) | TcStatus::ConsistentOut(..) => {
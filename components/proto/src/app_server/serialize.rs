@@ -23,11 +23,12 @@ use index_server::serialize::{ser_route_with_capacity, deser_route_with_capacity
 use crate::report::serialize::{ser_node_report, deser_node_report, 
     ser_node_report_mutation, deser_node_report_mutation};
 
-use crate::funder::messages::{RelayAddress, UserRequestSendFunds, 
-    ResponseReceived, ResponseSendFundsResult, ReceiptAck, 
+use crate::funder::messages::{RelayAddress, UserRequestSendFunds,
+    ResponseReceived, ResponseSendFundsResult, ReceiptAck,
     AddFriend, SetFriendName, SetFriendAddress,
-    SetFriendRemoteMaxDebt, ResetFriendChannel};
+    SetFriendRemoteMaxDebt, ResetFriendChannel, SetFriendAlias};
 use crate::funder::serialize::{ser_friends_route, deser_friends_route};
+use crate::funder::alias::FriendAlias;
 
 use super::messages::{AppServerToApp, AppToAppServer, 
                         NodeReport, NodeReportMutation};
@@ -171,6 +172,31 @@ fn deser_set_friend_name(set_friend_name_reader: &app_server_capnp::set_friend_n
     })
 }
 
+fn ser_set_friend_alias(set_friend_alias: &SetFriendAlias,
+                    set_friend_alias_builder: &mut app_server_capnp::set_friend_alias::Builder) {
+
+    write_public_key(&set_friend_alias.friend_public_key,
+              &mut set_friend_alias_builder.reborrow().init_friend_public_key());
+
+    set_friend_alias_builder.reborrow().init_alias().set_data(set_friend_alias.alias.as_ref());
+}
+
+fn deser_set_friend_alias(set_friend_alias_reader: &app_server_capnp::set_friend_alias::Reader)
+    -> Result<SetFriendAlias, SerializeError> {
+
+    let alias_bytes = set_friend_alias_reader.get_alias()?.get_data()?;
+    let mut alias_array = [0u8; FriendAlias::LEN];
+    if alias_bytes.len() != alias_array.len() {
+        return Err(SerializeError::InvalidAlias);
+    }
+    alias_array.copy_from_slice(alias_bytes);
+
+    Ok(SetFriendAlias {
+        friend_public_key: read_public_key(&set_friend_alias_reader.get_friend_public_key()?)?,
+        alias: FriendAlias::from(&alias_array),
+    })
+}
+
 fn ser_set_friend_relays(set_friend_address: &SetFriendAddress<Vec<RelayAddress>>,
                     set_friend_relays_builder: &mut app_server_capnp::set_friend_relays::Builder) {
 
@@ -331,25 +357,401 @@ fn deser_app_server_to_app(app_server_to_app_reader: &app_server_capnp::app_serv
 }
 
 
+fn ser_app_to_app_server(app_to_app_server: &AppToAppServer<RelayAddress, IndexServerAddress>,
+                    app_to_app_server_builder: &mut app_server_capnp::app_to_app_server::Builder) {
+
+    match app_to_app_server {
+        AppToAppServer::RequestSendFunds(user_request_send_funds) =>
+            ser_user_request_send_funds(user_request_send_funds,
+                                   &mut app_to_app_server_builder.reborrow().init_request_send_funds()),
+        AppToAppServer::ReceiptAck(receipt_ack) =>
+            ser_receipt_ack(receipt_ack,
+                                   &mut app_to_app_server_builder.reborrow().init_receipt_ack()),
+        AppToAppServer::AddFriend(add_friend) =>
+            ser_add_friend(add_friend,
+                                   &mut app_to_app_server_builder.reborrow().init_add_friend()),
+        AppToAppServer::SetFriendName(set_friend_name) =>
+            ser_set_friend_name(set_friend_name,
+                                   &mut app_to_app_server_builder.reborrow().init_set_friend_name()),
+        AppToAppServer::SetFriendRelays(set_friend_address) =>
+            ser_set_friend_relays(set_friend_address,
+                                   &mut app_to_app_server_builder.reborrow().init_set_friend_relays()),
+        AppToAppServer::SetFriendRemoteMaxDebt(set_friend_remote_max_debt) =>
+            ser_set_friend_remote_max_debt(set_friend_remote_max_debt,
+                                   &mut app_to_app_server_builder.reborrow().init_set_friend_remote_max_debt()),
+        AppToAppServer::ResetFriendChannel(reset_friend_channel) =>
+            ser_reset_friend_channel(reset_friend_channel,
+                                   &mut app_to_app_server_builder.reborrow().init_reset_friend_channel()),
+        AppToAppServer::SetFriendAlias(set_friend_alias) =>
+            ser_set_friend_alias(set_friend_alias,
+                                   &mut app_to_app_server_builder.reborrow().init_set_friend_alias()),
+    }
+}
+
+fn deser_app_to_app_server(app_to_app_server_reader: &app_server_capnp::app_to_app_server::Reader)
+    -> Result<AppToAppServer<RelayAddress, IndexServerAddress>, SerializeError> {
+
+    Ok(match app_to_app_server_reader.which()? {
+        app_server_capnp::app_to_app_server::RequestSendFunds(reader) =>
+            AppToAppServer::RequestSendFunds(deser_user_request_send_funds(&reader?)?),
+        app_server_capnp::app_to_app_server::ReceiptAck(reader) =>
+            AppToAppServer::ReceiptAck(deser_receipt_ack(&reader?)?),
+        app_server_capnp::app_to_app_server::AddFriend(reader) =>
+            AppToAppServer::AddFriend(deser_add_friend(&reader?)?),
+        app_server_capnp::app_to_app_server::SetFriendName(reader) =>
+            AppToAppServer::SetFriendName(deser_set_friend_name(&reader?)?),
+        app_server_capnp::app_to_app_server::SetFriendRelays(reader) =>
+            AppToAppServer::SetFriendRelays(deser_set_friend_relays(&reader?)?),
+        app_server_capnp::app_to_app_server::SetFriendRemoteMaxDebt(reader) =>
+            AppToAppServer::SetFriendRemoteMaxDebt(deser_set_friend_remote_max_debt(&reader?)?),
+        app_server_capnp::app_to_app_server::ResetFriendChannel(reader) =>
+            AppToAppServer::ResetFriendChannel(deser_reset_friend_channel(&reader?)?),
+        app_server_capnp::app_to_app_server::SetFriendAlias(reader) =>
+            AppToAppServer::SetFriendAlias(deser_set_friend_alias(&reader?)?),
+    })
+}
+
 // ---------------------------------------------------
 // ---------------------------------------------------
 
+/// Version prefixed to every serialized message, ahead of the message-kind tag and packed capnp
+/// payload. Bumped whenever the wire schema changes in a way that is not backwards compatible, so
+/// that a node can reject (rather than misparse) a message from an incompatible peer.
+const PROTOCOL_VERSION: u16 = 1;
+
+/// Identifies which top-level message type follows the envelope header. Capnp's own union tag
+/// already distinguishes `AppServerToApp` from `AppToAppServer` once a reader is constructed for
+/// the right root type - this tag lets `read_envelope` catch a mismatch (data for one serialized
+/// as the other) before it ever gets that far.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MessageKind {
+    AppServerToApp = 0,
+    AppToAppServer = 1,
+}
+
+fn write_envelope(kind: MessageKind, message: &capnp::message::Builder<capnp::message::HeapAllocator>) -> Vec<u8> {
+    let mut buff = Vec::new();
+    buff.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    buff.extend_from_slice(&(kind as u16).to_be_bytes());
+    serialize_packed::write_message(&mut buff, message).unwrap();
+    buff
+}
+
+fn read_envelope(expected_kind: MessageKind, data: &[u8])
+    -> Result<capnp::message::Reader<capnp::serialize::OwnedSegments>, SerializeError> {
+    if data.len() < 4 {
+        return Err(capnp::Error::failed("Envelope too short".to_owned()).into());
+    }
+    let version = u16::from_be_bytes([data[0], data[1]]);
+    if version != PROTOCOL_VERSION {
+        return Err(SerializeError::UnsupportedVersion);
+    }
+    let kind = u16::from_be_bytes([data[2], data[3]]);
+    if kind != expected_kind as u16 {
+        return Err(capnp::Error::failed(format!(
+            "Unexpected message kind: {}", kind)).into());
+    }
+    Ok(serialize_packed::read_message(
+        &mut &data[4..],
+        capnp::message::ReaderOptions::new(),
+    )?)
+}
+
 pub fn serialize_app_server_to_app(app_server_to_app: &AppServerToApp<RelayAddress, IndexServerAddress>) -> Vec<u8> {
-    // TODO
-    unimplemented!();
+    let mut message = capnp::message::Builder::new_default();
+    {
+        let mut app_server_to_app_builder = message.init_root::<app_server_capnp::app_server_to_app::Builder>();
+        ser_app_server_to_app(app_server_to_app, &mut app_server_to_app_builder);
+    }
+    write_envelope(MessageKind::AppServerToApp, &message)
 }
 
 pub fn deserialize_app_server_to_app(data: &[u8]) -> Result<AppServerToApp<RelayAddress, IndexServerAddress>, SerializeError> {
-    // TODO
-    unimplemented!();
+    let reader = read_envelope(MessageKind::AppServerToApp, data)?;
+    let app_server_to_app_reader = reader.get_root::<app_server_capnp::app_server_to_app::Reader>()?;
+    deser_app_server_to_app(&app_server_to_app_reader)
 }
 
-pub fn serialize_app_to_app_server(app_server_to_app: &AppToAppServer<RelayAddress, IndexServerAddress>) -> Vec<u8> {
-    // TODO
-    unimplemented!();
+pub fn serialize_app_to_app_server(app_to_app_server: &AppToAppServer<RelayAddress, IndexServerAddress>) -> Vec<u8> {
+    let mut message = capnp::message::Builder::new_default();
+    {
+        let mut app_to_app_server_builder = message.init_root::<app_server_capnp::app_to_app_server::Builder>();
+        ser_app_to_app_server(app_to_app_server, &mut app_to_app_server_builder);
+    }
+    write_envelope(MessageKind::AppToAppServer, &message)
 }
 
 pub fn deserialize_app_to_app_server(data: &[u8]) -> Result<AppToAppServer<RelayAddress, IndexServerAddress>, SerializeError> {
-    // TODO
-    unimplemented!();
+    let reader = read_envelope(MessageKind::AppToAppServer, data)?;
+    let app_to_app_server_reader = reader.get_root::<app_server_capnp::app_to_app_server::Reader>()?;
+    deser_app_to_app_server(&app_to_app_server_reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::identity::Signature;
+    use crypto::rand::CryptoRandom;
+    use crypto::test_utils::DummyRandom;
+    use crypto::uid::Uid;
+
+    /// Deterministic instance generators for the plain-data types making up
+    /// `AppServerToApp`/`AppToAppServer`, seeded from a `DummyRandom` so every test run produces
+    /// the exact same byte content - a diff that changes what gets serialized shows up as a
+    /// genuine assertion failure, not test flakiness.
+    fn rand_public_key<R: CryptoRandom>(rng: &mut R) -> PublicKey {
+        let mut bytes = [0u8; PublicKey::len()];
+        rng.fill(&mut bytes);
+        PublicKey::from(&bytes)
+    }
+
+    fn rand_uid<R: CryptoRandom>(rng: &mut R) -> Uid {
+        let mut bytes = [0u8; Uid::len()];
+        rng.fill(&mut bytes);
+        Uid::from(&bytes)
+    }
+
+    fn rand_signature<R: CryptoRandom>(rng: &mut R) -> Signature {
+        let mut bytes = [0u8; Signature::len()];
+        rng.fill(&mut bytes);
+        Signature::from(&bytes)
+    }
+
+    fn rand_alias<R: CryptoRandom>(rng: &mut R) -> FriendAlias {
+        let mut bytes = [0u8; FriendAlias::LEN];
+        rng.fill(&mut bytes);
+        FriendAlias::from(&bytes)
+    }
+
+    /// Printable-ish name, derived from the rng rather than hardcoded, for fields serialized as
+    /// capnp text.
+    fn rand_name<R: CryptoRandom>(rng: &mut R, len: usize) -> String {
+        let mut bytes = vec![0u8; len];
+        rng.fill(&mut bytes);
+        bytes.iter().map(|b| (b'a' + (b % 26)) as char).collect()
+    }
+
+    fn assert_app_server_to_app_round_trip(app_server_to_app: AppServerToApp<RelayAddress, IndexServerAddress>)
+        -> AppServerToApp<RelayAddress, IndexServerAddress> {
+        let data = serialize_app_server_to_app(&app_server_to_app);
+        deserialize_app_server_to_app(&data).unwrap()
+    }
+
+    fn assert_app_to_app_server_round_trip(app_to_app_server: AppToAppServer<RelayAddress, IndexServerAddress>)
+        -> AppToAppServer<RelayAddress, IndexServerAddress> {
+        let data = serialize_app_to_app_server(&app_to_app_server);
+        deserialize_app_to_app_server(&data).unwrap()
+    }
+
+    /// Every `AppServerToApp`/`AppToAppServer` variant constructible from fields whose shape is
+    /// visible in this snapshot is round-tripped through `serialize_*`/`deserialize_*` below, to
+    /// catch mismatches between a message's Rust struct and its capnp schema (e.g. a field added
+    /// to one but not the other). `ResponseReceived::Success`/`ResponseRoutesResult::Success`
+    /// are not covered: `Receipt`/`RouteWithCapacity` are defined in crates outside this snapshot
+    /// with no constructor visible here, so there is no way to build an instance to round-trip
+    /// without guessing at their private layout.
+    #[test]
+    fn test_response_received_failure_round_trip() {
+        let mut rng = DummyRandom::new(&[1u8]);
+        let request_id = rand_uid(&mut rng);
+        let public_key = rand_public_key(&mut rng);
+        let app_server_to_app = AppServerToApp::<RelayAddress, IndexServerAddress>::ResponseReceived(ResponseReceived {
+            request_id: request_id.clone(),
+            result: ResponseSendFundsResult::Failure(public_key.clone()),
+        });
+
+        match assert_app_server_to_app_round_trip(app_server_to_app) {
+            AppServerToApp::ResponseReceived(response_received) => {
+                assert_eq!(response_received.request_id, request_id);
+                match response_received.result {
+                    ResponseSendFundsResult::Failure(got_public_key) => assert_eq!(got_public_key, public_key),
+                    ResponseSendFundsResult::Success(_) => panic!("Expected Failure"),
+                }
+            },
+            _ => panic!("Expected ResponseReceived"),
+        }
+    }
+
+    #[test]
+    fn test_response_routes_failure_round_trip() {
+        let mut rng = DummyRandom::new(&[2u8]);
+        let request_id = rand_uid(&mut rng);
+        let app_server_to_app = AppServerToApp::<RelayAddress, IndexServerAddress>::ResponseRoutes(ClientResponseRoutes {
+            request_id: request_id.clone(),
+            result: ResponseRoutesResult::Failure,
+        });
+
+        match assert_app_server_to_app_round_trip(app_server_to_app) {
+            AppServerToApp::ResponseRoutes(client_response_routes) => {
+                assert_eq!(client_response_routes.request_id, request_id);
+                match client_response_routes.result {
+                    ResponseRoutesResult::Failure => {},
+                    ResponseRoutesResult::Success(_) => panic!("Expected Failure"),
+                }
+            },
+            _ => panic!("Expected ResponseRoutes"),
+        }
+    }
+
+    #[test]
+    fn test_receipt_ack_round_trip() {
+        let mut rng = DummyRandom::new(&[3u8]);
+        let request_id = rand_uid(&mut rng);
+        let receipt_signature = rand_signature(&mut rng);
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::ReceiptAck(ReceiptAck {
+            request_id: request_id.clone(),
+            receipt_signature: receipt_signature.clone(),
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::ReceiptAck(receipt_ack) => {
+                assert_eq!(receipt_ack.request_id, request_id);
+                assert_eq!(receipt_ack.receipt_signature, receipt_signature);
+            },
+            _ => panic!("Expected ReceiptAck"),
+        }
+    }
+
+    #[test]
+    fn test_set_friend_name_round_trip() {
+        let mut rng = DummyRandom::new(&[4u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        let name = rand_name(&mut rng, 12);
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::SetFriendName(SetFriendName {
+            friend_public_key: friend_public_key.clone(),
+            name: name.clone(),
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::SetFriendName(set_friend_name) => {
+                assert_eq!(set_friend_name.friend_public_key, friend_public_key);
+                assert_eq!(set_friend_name.name, name);
+            },
+            _ => panic!("Expected SetFriendName"),
+        }
+    }
+
+    #[test]
+    fn test_set_friend_remote_max_debt_round_trip() {
+        let mut rng = DummyRandom::new(&[5u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        let mut debt_bytes = [0u8; 16];
+        rng.fill(&mut debt_bytes);
+        let remote_max_debt = u128::from_be_bytes(debt_bytes);
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::SetFriendRemoteMaxDebt(SetFriendRemoteMaxDebt {
+            friend_public_key: friend_public_key.clone(),
+            remote_max_debt,
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::SetFriendRemoteMaxDebt(set_friend_remote_max_debt) => {
+                assert_eq!(set_friend_remote_max_debt.friend_public_key, friend_public_key);
+                assert_eq!(set_friend_remote_max_debt.remote_max_debt, remote_max_debt);
+            },
+            _ => panic!("Expected SetFriendRemoteMaxDebt"),
+        }
+    }
+
+    #[test]
+    fn test_reset_friend_channel_round_trip() {
+        let mut rng = DummyRandom::new(&[6u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        let reset_token = rand_signature(&mut rng);
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::ResetFriendChannel(ResetFriendChannel {
+            friend_public_key: friend_public_key.clone(),
+            reset_token: reset_token.clone(),
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::ResetFriendChannel(reset_friend_channel) => {
+                assert_eq!(reset_friend_channel.friend_public_key, friend_public_key);
+                assert_eq!(reset_friend_channel.reset_token, reset_token);
+            },
+            _ => panic!("Expected ResetFriendChannel"),
+        }
+    }
+
+    #[test]
+    fn test_add_friend_round_trip() {
+        let mut rng = DummyRandom::new(&[7u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        let name = rand_name(&mut rng, 8);
+        let mut balance_bytes = [0u8; 16];
+        rng.fill(&mut balance_bytes);
+        let balance = i128::from_be_bytes(balance_bytes);
+        // `RelayAddress` is defined outside this snapshot with no visible constructor, so this
+        // only exercises the (still real) empty-list path through ser_add_friend/deser_add_friend
+        // - a populated relay list is covered once that type's shape is available to generate.
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::AddFriend(AddFriend {
+            friend_public_key: friend_public_key.clone(),
+            address: Vec::new(),
+            name: name.clone(),
+            balance,
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::AddFriend(add_friend) => {
+                assert_eq!(add_friend.friend_public_key, friend_public_key);
+                assert!(add_friend.address.is_empty());
+                assert_eq!(add_friend.name, name);
+                assert_eq!(add_friend.balance, balance);
+            },
+            _ => panic!("Expected AddFriend"),
+        }
+    }
+
+    #[test]
+    fn test_set_friend_relays_round_trip() {
+        let mut rng = DummyRandom::new(&[8u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        // See test_add_friend_round_trip for why the relay list itself is left empty.
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::SetFriendRelays(SetFriendAddress {
+            friend_public_key: friend_public_key.clone(),
+            address: Vec::new(),
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::SetFriendRelays(set_friend_address) => {
+                assert_eq!(set_friend_address.friend_public_key, friend_public_key);
+                assert!(set_friend_address.address.is_empty());
+            },
+            _ => panic!("Expected SetFriendRelays"),
+        }
+    }
+
+    #[test]
+    fn test_set_friend_alias_round_trip() {
+        let mut rng = DummyRandom::new(&[9u8]);
+        let friend_public_key = rand_public_key(&mut rng);
+        let alias = rand_alias(&mut rng);
+        let app_to_app_server = AppToAppServer::<RelayAddress, IndexServerAddress>::SetFriendAlias(SetFriendAlias {
+            friend_public_key: friend_public_key.clone(),
+            alias,
+        });
+
+        match assert_app_to_app_server_round_trip(app_to_app_server) {
+            AppToAppServer::SetFriendAlias(set_friend_alias) => {
+                assert_eq!(set_friend_alias.friend_public_key, friend_public_key);
+                assert_eq!(set_friend_alias.alias, alias);
+            },
+            _ => panic!("Expected SetFriendAlias"),
+        }
+    }
+
+    /// Untrusted input must never panic a `deserialize_*` call - at worst it should return a
+    /// `SerializeError`. Walks a spread of deterministically-generated byte slices (lengths
+    /// from empty up through a full envelope header and a few capnp segment words) rather than
+    /// one or two hand-picked cases, since the interesting failures tend to be at boundary
+    /// lengths not worth enumerating by hand.
+    #[test]
+    fn test_deserialize_untrusted_bytes_does_not_panic() {
+        let mut rng = DummyRandom::new(&[0xffu8]);
+        for len in 0..256usize {
+            let mut garbage = vec![0u8; len];
+            rng.fill(&mut garbage);
+            let _ = deserialize_app_server_to_app(&garbage);
+            let _ = deserialize_app_to_app_server(&garbage);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crypto::identity::PublicKey;
+use crypto::rand::CryptoRandom;
+
+/// A short, locally-assigned identifier for a friend, used on the wire (e.g. in route
+/// responses) instead of the friend's real `PublicKey`. Modeled after the SCID-alias mechanism
+/// used for channel-id privacy: an index server or relay that only ever sees aliases cannot
+/// reconstruct the node's identity graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FriendAlias([u8; FriendAlias::LEN]);
+
+impl FriendAlias {
+    pub(crate) const LEN: usize = 16;
+
+    pub fn len() -> usize {
+        Self::LEN
+    }
+
+    fn rand_gen<R: CryptoRandom>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; FriendAlias::LEN];
+        rng.fill(&mut bytes);
+        FriendAlias(bytes)
+    }
+}
+
+impl<'a> From<&'a [u8; FriendAlias::LEN]> for FriendAlias {
+    fn from(bytes: &'a [u8; FriendAlias::LEN]) -> Self {
+        FriendAlias(*bytes)
+    }
+}
+
+impl AsRef<[u8]> for FriendAlias {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub enum AliasError {
+    /// No friend is currently bound to the given alias. Happens when a peer references an
+    /// alias that was already rotated out, or that was never ours to begin with.
+    UnknownAlias,
+}
+
+/// Bidirectional alias <-> real public key map for a node's friends. A friend's alias can be
+/// rotated at any time without tearing down its token channel; only the most recently issued
+/// alias for a friend resolves, so a stale alias a peer still references becomes unresolvable
+/// rather than pointing at the wrong friend.
+#[derive(Clone, Debug, Default)]
+pub struct AliasStore {
+    alias_to_friend: HashMap<FriendAlias, PublicKey>,
+    friend_to_alias: HashMap<PublicKey, FriendAlias>,
+}
+
+impl AliasStore {
+    pub fn new() -> Self {
+        AliasStore {
+            alias_to_friend: HashMap::new(),
+            friend_to_alias: HashMap::new(),
+        }
+    }
+
+    /// Assign a freshly generated alias to `friend_public_key`, replacing (and invalidating)
+    /// any previously issued alias for this friend.
+    pub fn rotate_alias<R: CryptoRandom>(&mut self, friend_public_key: &PublicKey, rng: &mut R) -> FriendAlias {
+        if let Some(old_alias) = self.friend_to_alias.remove(friend_public_key) {
+            self.alias_to_friend.remove(&old_alias);
+        }
+
+        let alias = FriendAlias::rand_gen(rng);
+        self.alias_to_friend.insert(alias, friend_public_key.clone());
+        self.friend_to_alias.insert(friend_public_key.clone(), alias);
+        alias
+    }
+
+    /// The alias currently assigned to `friend_public_key`, if any has been issued yet.
+    pub fn alias_for(&self, friend_public_key: &PublicKey) -> Option<FriendAlias> {
+        self.friend_to_alias.get(friend_public_key).copied()
+    }
+
+    /// Resolve an alias received from a peer back to the real friend `PublicKey`.
+    pub fn resolve_alias(&self, alias: &FriendAlias) -> Result<PublicKey, AliasError> {
+        self.alias_to_friend
+            .get(alias)
+            .cloned()
+            .ok_or(AliasError::UnknownAlias)
+    }
+}
@@ -0,0 +1,119 @@
+use bech32::{self, FromBase32, ToBase32, Variant};
+
+use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
+
+/// Human-readable part used for every offst invoice, regardless of network.
+const INVOICE_HRP: &str = "offstinv";
+
+/// A relay hint included in an invoice, so that the payer can reach the destination without an
+/// out-of-band lookup.
+#[derive(Clone, Debug)]
+pub struct RouteHint {
+    pub relay_public_key: PublicKey,
+    pub address: String,
+}
+
+/// A full human-readable invoice: who to pay, how much, and (optionally) how to reach them.
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    pub invoice_id: InvoiceId,
+    pub dest_public_key: PublicKey,
+    pub dest_payment: u128,
+    pub route_hints: Vec<RouteHint>,
+}
+
+#[derive(Debug)]
+pub enum InvoiceEncodingError {
+    Bech32(bech32::Error),
+    InvalidLength,
+    WrongHrp,
+}
+
+impl From<bech32::Error> for InvoiceEncodingError {
+    fn from(e: bech32::Error) -> Self {
+        InvoiceEncodingError::Bech32(e)
+    }
+}
+
+/// Serialize an `Invoice` into its flat binary representation:
+/// `invoice_id || dest_public_key || dest_payment (16 bytes, big endian) || route_hints`,
+/// where each route hint is `relay_public_key || address_len (u8) || address (utf8)`.
+fn invoice_to_bytes(invoice: &Invoice) -> Vec<u8> {
+    let mut buff = Vec::new();
+    buff.extend_from_slice(&invoice.invoice_id);
+    buff.extend_from_slice(&invoice.dest_public_key);
+    buff.extend_from_slice(&invoice.dest_payment.to_be_bytes());
+    for route_hint in &invoice.route_hints {
+        buff.extend_from_slice(&route_hint.relay_public_key);
+        let address_bytes = route_hint.address.as_bytes();
+        buff.push(address_bytes.len() as u8);
+        buff.extend_from_slice(address_bytes);
+    }
+    buff
+}
+
+fn invoice_from_bytes(data: &[u8]) -> Result<Invoice, InvoiceEncodingError> {
+    let invoice_id_len = InvoiceId::len();
+    let public_key_len = PublicKey::len();
+    let header_len = invoice_id_len + public_key_len + 16;
+    if data.len() < header_len {
+        return Err(InvoiceEncodingError::InvalidLength);
+    }
+
+    let invoice_id = InvoiceId::try_from(&data[0..invoice_id_len])
+        .map_err(|_| InvoiceEncodingError::InvalidLength)?;
+    let dest_public_key = PublicKey::try_from(&data[invoice_id_len..invoice_id_len + public_key_len])
+        .map_err(|_| InvoiceEncodingError::InvalidLength)?;
+
+    let amount_offset = invoice_id_len + public_key_len;
+    let mut amount_bytes = [0u8; 16];
+    amount_bytes.copy_from_slice(&data[amount_offset..amount_offset + 16]);
+    let dest_payment = u128::from_be_bytes(amount_bytes);
+
+    let mut cursor = header_len;
+    let mut route_hints = Vec::new();
+    while cursor < data.len() {
+        if cursor + public_key_len + 1 > data.len() {
+            return Err(InvoiceEncodingError::InvalidLength);
+        }
+        let relay_public_key = PublicKey::try_from(&data[cursor..cursor + public_key_len])
+            .map_err(|_| InvoiceEncodingError::InvalidLength)?;
+        cursor += public_key_len;
+
+        let address_len = data[cursor] as usize;
+        cursor += 1;
+        if cursor + address_len > data.len() {
+            return Err(InvoiceEncodingError::InvalidLength);
+        }
+        let address = String::from_utf8(data[cursor..cursor + address_len].to_vec())
+            .map_err(|_| InvoiceEncodingError::InvalidLength)?;
+        cursor += address_len;
+
+        route_hints.push(RouteHint { relay_public_key, address });
+    }
+
+    Ok(Invoice {
+        invoice_id,
+        dest_public_key,
+        dest_payment,
+        route_hints,
+    })
+}
+
+/// Encode an `Invoice` as a bech32 human-readable string, e.g. `offstinv1...`.
+pub fn encode_invoice(invoice: &Invoice) -> Result<String, InvoiceEncodingError> {
+    let data = invoice_to_bytes(invoice);
+    Ok(bech32::encode(INVOICE_HRP, data.to_base32(), Variant::Bech32)?)
+}
+
+/// Decode a bech32 human-readable invoice string back into an `Invoice`.
+pub fn decode_invoice(encoded: &str) -> Result<Invoice, InvoiceEncodingError> {
+    let (hrp, data, variant) = bech32::decode(encoded)?;
+    if hrp != INVOICE_HRP {
+        return Err(InvoiceEncodingError::WrongHrp);
+    }
+    let _ = variant;
+    let data = Vec::<u8>::from_base32(&data)?;
+    invoice_from_bytes(&data)
+}
@@ -0,0 +1,86 @@
+use crypto::identity::{verify_signature, PublicKey, Signature};
+use crypto::uid::Uid;
+
+/// A single party's partial acknowledgement of a receipt, as part of a threshold/multisig
+/// `ThresholdReceiptAck`. `signer_public_key` identifies which of the configured signers this
+/// partial signature belongs to.
+#[derive(Clone, Debug)]
+pub struct PartialReceiptAck {
+    pub signer_public_key: PublicKey,
+    pub receipt_signature: Signature,
+}
+
+/// A receipt acknowledgement requiring `threshold` out of a fixed set of signers to approve,
+/// rather than the single signature carried by a plain `ReceiptAck`. Used when a payment's
+/// receipt should be co-signed by multiple parties (e.g. a multisig wallet backing an app).
+#[derive(Clone, Debug)]
+pub struct ThresholdReceiptAck {
+    pub request_id: Uid,
+    pub threshold: u8,
+    pub partial_acks: Vec<PartialReceiptAck>,
+}
+
+#[derive(Debug)]
+pub enum ThresholdReceiptAckError {
+    /// Fewer partial acknowledgements were collected than `threshold` requires.
+    NotEnoughSignatures,
+    /// The same signer appears more than once among the partial acknowledgements.
+    DuplicateSigner,
+    /// A signer not present in `authorized_signers` contributed a partial acknowledgement.
+    UnauthorizedSigner,
+    /// `receipt_signature` does not verify against `receipt_bytes` under `signer_public_key`.
+    InvalidSignature,
+}
+
+impl ThresholdReceiptAck {
+    pub fn new(request_id: Uid, threshold: u8) -> Self {
+        ThresholdReceiptAck {
+            request_id,
+            threshold,
+            partial_acks: Vec::new(),
+        }
+    }
+
+    pub fn add_partial_ack(&mut self, partial_ack: PartialReceiptAck) {
+        self.partial_acks.push(partial_ack);
+    }
+
+    /// Check whether enough valid, distinct, authorized partial acknowledgements have been
+    /// collected to consider the receipt acknowledged. `receipt_bytes` is the serialized receipt
+    /// each `receipt_signature` is expected to sign; callers are responsible for serializing it
+    /// the same way on both the signing and verifying side.
+    pub fn verify(
+        &self,
+        authorized_signers: &[PublicKey],
+        receipt_bytes: &[u8],
+    ) -> Result<(), ThresholdReceiptAckError> {
+        let mut seen = Vec::<&PublicKey>::new();
+        for partial_ack in &self.partial_acks {
+            if !authorized_signers.contains(&partial_ack.signer_public_key) {
+                return Err(ThresholdReceiptAckError::UnauthorizedSigner);
+            }
+            if seen.contains(&&partial_ack.signer_public_key) {
+                return Err(ThresholdReceiptAckError::DuplicateSigner);
+            }
+            if !verify_signature(receipt_bytes, &partial_ack.signer_public_key, &partial_ack.receipt_signature) {
+                return Err(ThresholdReceiptAckError::InvalidSignature);
+            }
+            seen.push(&partial_ack.signer_public_key);
+        }
+
+        if seen.len() < self.threshold as usize {
+            return Err(ThresholdReceiptAckError::NotEnoughSignatures);
+        }
+
+        Ok(())
+    }
+}
+
+// Note on scope: the review that prompted this fix also asked for a `MultisigReceiptAck` capnp
+// message (with `ser_`/`deser_` functions and `SerializeError`-based duplicate rejection) and
+// `AddFriend` wiring for a per-friend signer set. Every other message this crate serializes is
+// generated from a `.capnp` schema file that isn't part of this snapshot (only the generated
+// `app_server_capnp`/`funder_capnp` modules are referenced, never defined), so there is no schema
+// here to extend without guessing at field layouts `ser_`/`deser_` would then silently get wrong.
+// That part is left undone rather than faked; `verify` above is fixed to do real, honest
+// signature checking in the meantime.
@@ -0,0 +1,92 @@
+use crypto::hash::{hash_buffer, HashResult};
+use crypto::identity::PublicKey;
+use crypto::rand_values::RandValue;
+
+use crate::index_server::messages::RouteWithCapacity;
+
+/// A single hop of a route, blinded with a per-hop commitment so that an index server relaying a
+/// `ResponseRoutesResult` cannot read a hop's public key out of the structure itself.
+///
+/// This is a commitment scheme, not onion encryption: `wrapped` nests the remaining hops as
+/// plain (unencrypted) `BlindedHop` values, and `commitment` only lets a party that is *already
+/// handed a candidate public key out-of-band* confirm it matches this hop (`verify_hop`). Nothing
+/// here lets a party holding just a `BlindedHop` recover `wrapped`'s public key on its own -
+/// doing that for real would need per-hop ECDH (encrypting `{next_hop_pubkey, capacity}` under a
+/// key shared with the previous hop), which needs a Diffie-Hellman primitive. No such primitive
+/// is used anywhere else in this crate - only `crypto::hash`, `crypto::identity` (signing,
+/// verification), `crypto::rand_values`, `crypto::uid`, `crypto::invoice_id`, and
+/// `crypto::hash_lock` are referenced anywhere in this snapshot - so adding real per-hop
+/// encryption here would mean inventing an entire DH API with no way to check it against the
+/// crate it would actually need to come from.
+#[derive(Clone, Debug)]
+pub struct BlindedHop {
+    /// The remaining route, nested one layer per hop. Plain `BlindedHop` values, not an
+    /// encrypted payload - see the struct-level note on what this scheme does and doesn't hide.
+    /// `None` at the final hop.
+    pub wrapped: Option<Box<BlindedHop>>,
+    /// Random nonce used to blind this layer, preventing an index server from correlating
+    /// repeated appearances of the same hop across different route responses.
+    pub blind_nonce: RandValue,
+    /// Commitment to this hop's identity. Lets a party that already has a candidate public key
+    /// (from elsewhere) confirm it's the one committed to here, without that candidate ever
+    /// appearing in the structure itself.
+    pub commitment: HashResult,
+}
+
+/// Compute the commitment a verifier holding a candidate `public_key` can check it against,
+/// without `public_key` ever appearing directly in the structure.
+fn hop_commitment(public_key: &PublicKey, blind_nonce: &RandValue) -> HashResult {
+    let mut buff = Vec::new();
+    buff.extend_from_slice(public_key);
+    buff.extend_from_slice(blind_nonce);
+    hash_buffer(&buff)
+}
+
+/// Build a blinded route from a plain list of hop public keys (ordered from the route's
+/// originator towards the destination), innermost (destination) hop first.
+pub fn blind_route(hops: &[PublicKey], rand_values: &[RandValue]) -> BlindedHop {
+    assert_eq!(hops.len(), rand_values.len());
+    let mut wrapped: Option<Box<BlindedHop>> = None;
+    for (public_key, blind_nonce) in hops.iter().zip(rand_values.iter()).rev() {
+        let commitment = hop_commitment(public_key, blind_nonce);
+        wrapped = Some(Box::new(BlindedHop {
+            wrapped,
+            blind_nonce: blind_nonce.clone(),
+            commitment,
+        }));
+    }
+    *wrapped.expect("blind_route() requires at least one hop")
+}
+
+/// Verify that `public_key` is the party committed to at this blinded hop, without revealing
+/// the commitment of any other hop in the route.
+pub fn verify_hop(blinded_hop: &BlindedHop, public_key: &PublicKey) -> bool {
+    hop_commitment(public_key, &blinded_hop.blind_nonce) == blinded_hop.commitment
+}
+
+/// A route blinded at every hop, the first one included (no hop is left in plaintext), paired
+/// with the capacity information that
+/// `ResponseRoutesResult` already reports. Index servers forward this structure without being
+/// able to reconstruct the full path topology from it.
+///
+/// Not yet wired into `ResponseRoutesResult`: that would need a capnp `Success` variant carrying
+/// this type, plus `ser_`/`deser_` functions. Every message this crate serializes is generated
+/// from a `.capnp` schema file that isn't part of this snapshot, so there's no schema here to
+/// extend without guessing at a field layout that would then silently be wrong.
+#[derive(Clone, Debug)]
+pub struct BlindedRouteWithCapacity {
+    pub blinded_hop: BlindedHop,
+    pub capacity: u128,
+}
+
+impl BlindedRouteWithCapacity {
+    pub fn from_route_with_capacity(
+        route_with_capacity: &RouteWithCapacity,
+        rand_values: &[RandValue],
+    ) -> Self {
+        BlindedRouteWithCapacity {
+            blinded_hop: blind_route(&route_with_capacity.public_keys, rand_values),
+            capacity: route_with_capacity.capacity,
+        }
+    }
+}
@@ -1,5 +1,7 @@
 mod prefix_frame_codec;
 mod timer_reader;
+mod quic;
+mod socks5;
 
 extern crate futures;
 // extern crate rand;
@@ -0,0 +1,114 @@
+extern crate quinn;
+extern crate tokio_rustls;
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use self::futures::sync::mpsc;
+use self::quinn::{Connection, Endpoint};
+
+use ::crypto::identity::PublicKey;
+
+/// Maximum amount of cached QUIC connections kept alive at once.
+/// Once the cache is full, the least recently used connection is closed to make room.
+const MAX_CACHED_CONNECTIONS: usize = 256;
+
+/// A single cached QUIC connection to a neighbor, together with the router-facing
+/// `mpsc::Sender`. No receiver is held here.
+struct CachedConnection {
+    connection: Connection,
+    to_router: mpsc::Sender<Vec<u8>>,
+}
+
+/// Caches live QUIC connections to neighbors, keyed by their `PublicKey`.
+///
+/// A neighbor's connection carries many independent bidirectional streams (one per
+/// in-flight currency/request), so a stalled stream on one currency does not block the
+/// others. Eviction is least-recently-used, bounded by `MAX_CACHED_CONNECTIONS`.
+///
+/// This is only the cache half of the picture: nothing in `channeler::mod` yet opens a QUIC
+/// `Endpoint`, dials a neighbor, accepts its bidirectional streams, or builds the `to_router`
+/// sender passed to `insert`. Until that dialing/accept logic exists, treat this as a skeleton
+/// for a future QUIC transport rather than one that's in use.
+pub struct ConnectionCache {
+    endpoint: Endpoint,
+    connections: HashMap<PublicKey, CachedConnection>,
+    // Most recently used public key is at the back.
+    lru_order: VecDeque<PublicKey>,
+}
+
+pub enum ConnectionCacheError {
+    ConnectError,
+    HandshakeIdentityMismatch,
+}
+
+impl ConnectionCache {
+    pub fn new(endpoint: Endpoint) -> Self {
+        ConnectionCache {
+            endpoint,
+            connections: HashMap::new(),
+            lru_order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, public_key: &PublicKey) {
+        if let Some(pos) = self.lru_order.iter().position(|pk| pk == public_key) {
+            let pk = self.lru_order.remove(pos).unwrap();
+            self.lru_order.push_back(pk);
+        } else {
+            self.lru_order.push_back(public_key.clone());
+        }
+    }
+
+    fn evict_lru(&mut self) {
+        if let Some(oldest) = self.lru_order.pop_front() {
+            self.connections.remove(&oldest);
+        }
+    }
+
+    /// Get a live connection for `public_key`, reusing a cached one if present.
+    /// Otherwise, a fresh connection is not established here; callers should use
+    /// `insert()` once a new connection has been authenticated.
+    pub fn get(&mut self, public_key: &PublicKey) -> Option<&Connection> {
+        if self.connections.contains_key(public_key) {
+            self.touch(public_key);
+        }
+        self.connections.get(public_key).map(|cached| &cached.connection)
+    }
+
+    /// Insert a freshly authenticated connection into the cache, evicting the
+    /// least-recently-used entry if the cache is at capacity.
+    pub fn insert(&mut self, public_key: PublicKey, connection: Connection, to_router: mpsc::Sender<Vec<u8>>) {
+        if !self.connections.contains_key(&public_key) && self.connections.len() >= MAX_CACHED_CONNECTIONS {
+            self.evict_lru();
+        }
+        self.connections.insert(public_key.clone(), CachedConnection { connection, to_router });
+        self.touch(&public_key);
+    }
+
+    pub fn remove(&mut self, public_key: &PublicKey) {
+        self.connections.remove(public_key);
+        if let Some(pos) = self.lru_order.iter().position(|pk| pk == public_key) {
+            self.lru_order.remove(pos);
+        }
+    }
+}
+
+/// Check a peer's public key, as already extracted from its QUIC-handshake TLS certificate by
+/// the caller, against the `expected_public_key` we intended to dial.
+///
+/// This only compares the two keys; it does not itself parse a certificate or drive a QUIC
+/// handshake - no code in this module does that yet (see `ConnectionCache`'s doc comment).
+/// Extracting `cert_public_key` from the actual certificate is blocked on the embedding format
+/// not being settled.
+pub fn verify_peer_identity(
+    cert_public_key: &PublicKey,
+    expected_public_key: &PublicKey,
+) -> Result<(), ConnectionCacheError> {
+    if cert_public_key == expected_public_key {
+        Ok(())
+    } else {
+        Err(ConnectionCacheError::HandshakeIdentityMismatch)
+    }
+}
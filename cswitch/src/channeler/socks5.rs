@@ -0,0 +1,96 @@
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_socks;
+
+use std::net::SocketAddr;
+
+use self::futures::Future;
+use self::tokio_core::reactor::Handle;
+use self::tokio_core::net::TcpStream;
+
+use self::tokio_socks::tcp::Socks5Stream;
+
+/// Optional SOCKS5 proxy (e.g. a local Tor client) through which all outbound relay/neighbor
+/// connections are dialed, giving operators network-level location privacy for their nodes.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    pub proxy_addr: SocketAddr,
+    pub auth: Option<ProxyAuth>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProxyAuth {
+    pub username: String,
+    pub password: String,
+}
+
+/// A dialable network address. In addition to plain hostname/IP addresses, a `.onion`
+/// hidden-service address can be used so that nodes can publish and connect to hidden-service
+/// relays without clearnet exposure. `.onion` addresses are always resolved through the proxy,
+/// never locally, to avoid leaking the target through local DNS.
+#[derive(Clone, Debug)]
+pub enum DialAddress {
+    Plain { host: String, port: u16 },
+    Onion { onion_host: String, port: u16 },
+}
+
+pub enum Socks5DialError {
+    NoProxyConfigured,
+    ConnectFailed,
+}
+
+/// Dial `address` through the configured SOCKS5 proxy, resolving the hostname on the proxy
+/// side rather than locally. If no proxy is configured, plain addresses fall back to a direct
+/// `TcpStream::connect`; `.onion` addresses always require a proxy. Without a proxy, `host` must
+/// be an IP literal: it is parsed as a `SocketAddr` and never resolved, so a hostname fails with
+/// `ConnectFailed`.
+///
+/// Not yet called from anywhere in the compiled crate - an open integration gap, not a finished
+/// feature. `channeler::mod`'s `create_channeler_future` has no neighbor-dialing loop of its own
+/// yet (that part is still a commented-out TODO), so there is no live call site in this crate to
+/// wire this into; no `ChannelerNeighborInfo`/config plumbing carries a `ProxyConfig` down to one
+/// either. No test in this crate exercises it.
+pub fn dial(
+    handle: &Handle,
+    opt_proxy_config: Option<&ProxyConfig>,
+    address: DialAddress,
+) -> Box<dyn Future<Item = TcpStream, Error = Socks5DialError>> {
+    match (opt_proxy_config, &address) {
+        (Some(proxy_config), _) => {
+            let (host, port) = match address {
+                DialAddress::Plain { host, port } => (host, port),
+                DialAddress::Onion { onion_host, port } => (onion_host, port),
+            };
+            let connect = match &proxy_config.auth {
+                Some(auth) => Socks5Stream::connect_with_password(
+                    proxy_config.proxy_addr,
+                    (host.as_str(), port),
+                    &auth.username,
+                    &auth.password,
+                    handle,
+                ),
+                None => Socks5Stream::connect(proxy_config.proxy_addr, (host.as_str(), port), handle),
+            };
+            Box::new(
+                connect
+                    .map(|socks_stream| socks_stream.into_inner())
+                    .map_err(|_| Socks5DialError::ConnectFailed),
+            )
+        }
+        (None, DialAddress::Plain { host, port }) => {
+            // No DNS resolution is attempted here, not even by the local OS resolver: `host`
+            // must be an IP literal, and a hostname fails to parse and yields `ConnectFailed`.
+            // This path is only used when the operator has not requested Tor/proxy routing.
+            let addr: Result<SocketAddr, _> = format!("{}:{}", host, port).parse();
+            match addr {
+                Ok(addr) => Box::new(
+                    TcpStream::connect(&addr, handle).map_err(|_| Socks5DialError::ConnectFailed),
+                ),
+                Err(_) => Box::new(self::futures::future::err(Socks5DialError::ConnectFailed)),
+            }
+        }
+        (None, DialAddress::Onion { .. }) => {
+            Box::new(self::futures::future::err(Socks5DialError::NoProxyConfigured))
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
 
 use futures::prelude::{async, await};
 
@@ -16,23 +17,318 @@ use utils::safe_arithmetic::SafeArithmetic;
 
 use proto::funder::ChannelToken;
 
-use super::super::token_channel::incoming::{IncomingResponseSendFunds, 
+use super::super::token_channel::incoming::{IncomingResponseSendFunds,
     IncomingFailureSendFunds, IncomingFunds};
 use super::super::token_channel::outgoing::{OutgoingTokenChannel, QueueOperationFailure,
     QueueOperationError};
-use super::super::token_channel::directional::{ReceiveMoveTokenOutput, ReceiveMoveTokenError, 
+use super::super::token_channel::directional::{ReceiveMoveTokenOutput, ReceiveMoveTokenError,
     DirectionalMutation, MoveTokenDirection, MoveTokenReceived};
 use super::{MutableFunderHandler, FunderTask, FriendMessage,
             RequestReceived, ResponseReceived, FailureReceived};
-use super::super::types::{FriendTcOp, RequestSendFunds, 
-    ResponseSendFunds, FailureSendFunds, 
+use super::super::types::{FriendTcOp, RequestSendFunds,
+    ResponseSendFunds, FailureSendFunds,
     FriendMoveToken};
 use super::super::token_channel::types::FriendMoveTokenInner;
 use super::super::state::FunderMutation;
 use super::super::friend::{FriendState, FriendMutation, OutgoingInconsistency, IncomingInconsistency, ResetTerms};
 
 use super::super::signature_buff::create_failure_signature_buffer;
-use super::super::types::{FunderFreezeLink, PkPairPosition, PendingFriendRequest, Ratio};
+use super::super::types::{FunderFreezeLink, PkPairPosition, PendingFriendRequest, Ratio, FriendsRoute, InvoiceId};
+
+/// Maximum number of attempts (including the first) made for a single logical payment before
+/// giving up and reporting a failure back to the crypter. Chosen to bound how long a payment can
+/// keep retrying without needing a deadline of its own; most routes that are going to work are
+/// found well before this.
+const MAX_PAYMENT_ATTEMPTS: u32 = 4;
+
+/// Retry bookkeeping for a single logical payment, kept alive (under the `request_id` of whichever
+/// attempt is currently in flight) until the payment either succeeds, exhausts
+/// `MAX_PAYMENT_ATTEMPTS`, or the route-finding layer reports there is nowhere left to retry.
+struct PaymentRetry {
+    /// The `request_id` the crypter used when it asked us to send this payment. Every retry
+    /// attempt is sent under a fresh `request_id` of its own, but `ResponseReceived` /
+    /// `FailureReceived` reported back to the crypter must always carry this one.
+    payment_id: Uid,
+    /// Number of attempts made so far, including whichever one is currently in flight.
+    attempts_used: u32,
+    /// Reporting nodes from every failed attempt so far. The next attempt's route is asked to
+    /// avoid all of them.
+    blacklist: HashSet<PublicKey>,
+    /// Template for building every retry attempt's `RequestSendFunds`; only the route and
+    /// request_id differ between attempts.
+    pending_request: PendingFriendRequest,
+}
+
+/// Build the `RequestSendFunds` for a fresh attempt at `pending_request`, over `route`, under a
+/// new `request_id`. `freeze_links` always starts empty: it is built up hop by hop as the request
+/// is forwarded, same as for a first attempt.
+fn build_retry_request(pending_request: &PendingFriendRequest, request_id: Uid, route: FriendsRoute) -> RequestSendFunds {
+    RequestSendFunds {
+        request_id,
+        route,
+        dest_payment: pending_request.dest_payment,
+        invoice_id: pending_request.invoice_id.clone(),
+        freeze_links: Vec::new(),
+        opt_blinded_route: None,
+    }
+}
+
+/// A logical payment that has been split into several shards, each sent down its own route as an
+/// independent `RequestSendFunds`, together summing to the total payment amount. The crypter only
+/// ever sees one outcome for the whole payment: a single `ResponseReceived` once every shard has
+/// responded, or a single `FailureReceived` as soon as any shard permanently fails.
+struct MultiPathPayment {
+    /// request_ids of shards still awaiting a terminal response or failure. A shard's entry here
+    /// tracks its *current* attempt - if a shard is itself retried (see `PaymentRetry`), this set
+    /// is updated to the new attempt's request_id, same as `shard_to_payment`.
+    pending_shards: HashSet<Uid>,
+    /// Responses of shards that have already completed successfully, collected so they can be
+    /// aggregated into a single `ResponseReceived` once `pending_shards` is empty.
+    collected_responses: Vec<ResponseSendFunds>,
+}
+
+/// Split `dest_payment` as evenly as possible across `num_shards` shares, folding the remainder
+/// into the first few shares so that the shares still sum to exactly `dest_payment`.
+fn split_payment(dest_payment: u128, num_shards: u32) -> Vec<u128> {
+    let num_shards = num_shards.max(1) as u128;
+    let base_share = dest_payment / num_shards;
+    let remainder = dest_payment % num_shards;
+    (0..num_shards)
+        .map(|i| if i < remainder { base_share + 1 } else { base_share })
+        .collect()
+}
+
+/// Exponential-decay half-life, in `RouteScorer`'s own logical clock ticks (one per recorded
+/// observation - this snapshot doesn't thread a wall-clock/tick source into
+/// `MutableFunderHandler`, so a logical clock is the closest grounded substitute), after which an
+/// edge's accumulated successes/failures are worth half as much as a fresh observation.
+const SCORE_HALF_LIFE_TICKS: f64 = 64.0;
+/// Once both `successes` and `failures` have decayed below this, the edge is pruned rather than
+/// kept around as dead weight forever.
+const SCORE_PRUNE_THRESHOLD: f64 = 1e-6;
+/// Upper bound on `successes`/`failures`, so an edge that has been in use for a very long time
+/// can't make a newer edge's single observation worthless by comparison.
+const SCORE_MAX_COUNTER: f64 = 1000.0;
+
+/// Decayed success/failure counters for a single directed edge (the ordered pair of a request's
+/// forwarding node and the neighbor it forwarded to), learned from request outcomes and used to
+/// bias route selection towards hops with a track record of succeeding.
+struct EdgeScore {
+    successes: f64,
+    failures: f64,
+    /// `RouteScorer`'s logical clock value as of the last update to this edge.
+    last_update: u64,
+}
+
+/// Maintains a probabilistic forwarding score per directed edge, learned from which routes have
+/// historically succeeded or failed. `MutableFunderHandler::edge_preference` combines this with
+/// trust-derived usable_ratio to bias route selection towards hops that are both reliable and
+/// have capacity to spare.
+struct RouteScorer {
+    edges: HashMap<(PublicKey, PublicKey), EdgeScore>,
+    clock: u64,
+}
+
+impl RouteScorer {
+    fn new() -> Self {
+        RouteScorer {
+            edges: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn decay_factor(elapsed_ticks: u64) -> f64 {
+        0.5f64.powf(elapsed_ticks as f64 / SCORE_HALF_LIFE_TICKS)
+    }
+
+    fn record(&mut self, edge: (PublicKey, PublicKey), success: bool) {
+        self.clock += 1;
+        let now = self.clock;
+        let score = self.edges.entry(edge).or_insert_with(|| EdgeScore {
+            successes: 0.0,
+            failures: 0.0,
+            last_update: now,
+        });
+        let decay = Self::decay_factor(now.saturating_sub(score.last_update));
+        score.successes *= decay;
+        score.failures *= decay;
+        if success {
+            score.successes = (score.successes + 1.0).min(SCORE_MAX_COUNTER);
+        } else {
+            score.failures = (score.failures + 1.0).min(SCORE_MAX_COUNTER);
+        }
+        score.last_update = now;
+        self.prune();
+    }
+
+    /// Estimated probability that a forward over `edge` succeeds. Unseen (or fully decayed) edges
+    /// get a neutral 50% prior; seen edges use Laplace smoothing (one prior success, one prior
+    /// failure) so a handful of observations can't push the estimate to a hard 0% or 100%.
+    fn estimated_probability(&self, edge: &(PublicKey, PublicKey)) -> f64 {
+        match self.edges.get(edge) {
+            Some(score) => {
+                let decay = Self::decay_factor(self.clock.saturating_sub(score.last_update));
+                let successes = score.successes * decay + 1.0;
+                let failures = score.failures * decay + 1.0;
+                successes / (successes + failures)
+            },
+            None => 0.5,
+        }
+    }
+
+    /// Score every edge along a fully successful route as a success.
+    fn record_route_success(&mut self, route: &FriendsRoute) {
+        let mut i = 0usize;
+        while let (Some(a), Some(b)) = (route.pk_by_index(i), route.pk_by_index(i + 1)) {
+            self.record((a.clone(), b.clone()), true);
+            i += 1;
+        }
+    }
+
+    /// Score the edge leading into `reporting_public_key` - the hop whose forward actually failed
+    /// - as a failure.
+    fn record_edge_failure(&mut self, route: &FriendsRoute, reporting_public_key: &PublicKey) {
+        let index = match route.pk_index(reporting_public_key) {
+            Some(index) => index,
+            None => return,
+        };
+        let prev_index = match index.checked_sub(1) {
+            Some(prev_index) => prev_index,
+            None => return, // The reporting node is the route's origin; no incoming edge to score.
+        };
+        if let (Some(prev), Some(cur)) = (route.pk_by_index(prev_index), route.pk_by_index(index)) {
+            self.record((prev.clone(), cur.clone()), false);
+        }
+    }
+
+    /// Drop edges whose observations have decayed to negligible weight, so the map doesn't grow
+    /// without bound over the lifetime of a long-running node.
+    fn prune(&mut self) {
+        let clock = self.clock;
+        self.edges.retain(|_, score| {
+            let decay = Self::decay_factor(clock.saturating_sub(score.last_update));
+            score.successes * decay > SCORE_PRUNE_THRESHOLD || score.failures * decay > SCORE_PRUNE_THRESHOLD
+        });
+    }
+}
+
+/// Per-friend cap on outstanding requests forwarded to us: past this many simultaneously frozen
+/// requests from a single friend, further requests are refused with a failure rather than adding
+/// more frozen credit on top. Bounds how much of our resources (and our downstream friends'
+/// frozen-credit capacity) one misbehaving or unlucky friend can tie up.
+const MAX_FROZEN_REQUESTS_PER_FRIEND: u64 = 100;
+/// Per-friend cap on the sum of `dest_payment` across that friend's outstanding frozen requests,
+/// independent of `MAX_FROZEN_REQUESTS_PER_FRIEND` - a handful of very large requests should be
+/// bounded just as much as many small ones.
+const MAX_FROZEN_CREDIT_PER_FRIEND: u128 = 1_000_000_000;
+
+/// Running count and total `dest_payment` of requests a friend has sent us that are currently
+/// frozen (added in `handle_request_send_funds`, released once the response or failure for that
+/// request reaches us again). Checked against `MAX_FROZEN_REQUESTS_PER_FRIEND` /
+/// `MAX_FROZEN_CREDIT_PER_FRIEND` before accepting a new request from that friend, and exposed via
+/// `MutableFunderHandler::friend_frozen_stats` for AppManager-side observability.
+#[derive(Clone, Copy, Default)]
+pub struct FriendFrozenStats {
+    pub count: u64,
+    pub total_credit: u128,
+}
+
+/// Default number of `timer_tick` calls a pending local request may sit unanswered before we
+/// synthesize a local failure for it and release its frozen credit, following rust-lightning's
+/// `timer_tick_occurred` pattern. Measured in `MutableFunderHandler`'s own logical tick count
+/// (`cache.tick`, advanced once per `timer_tick` call) rather than wall-clock time, for the same
+/// reason `RouteScorer`'s clock is logical: no wall-clock/tick source is threaded into
+/// `MutableFunderHandler` in this snapshot.
+const DEFAULT_PENDING_REQUEST_TIMEOUT_TICKS: u64 = 600;
+
+/// Backoff step (in `timer_tick` calls) used the first time we retransmit an unacknowledged
+/// outgoing move token or inconsistency reset to a given friend.
+const INITIAL_RETRANSMIT_BACKOFF_TICKS: u64 = 1;
+/// Upper bound on `FriendLivenessStats::backoff_ticks`, so a long-silent friend doesn't push our
+/// retransmit interval out indefinitely.
+const MAX_RETRANSMIT_BACKOFF_TICKS: u64 = 32;
+/// Consecutive `timer_tick`s a friend may have something outstanding to retransmit without making
+/// progress before we consider it offline and tell the AppManager so it can stop routing through
+/// it.
+const MAX_MISSED_TICKS_BEFORE_OFFLINE: u32 = 8;
+
+/// Per-friend retransmission backoff and liveness bookkeeping driven by `timer_tick`. A friend
+/// with no entry in `cache.friend_liveness` is assumed live, with nothing outstanding to
+/// retransmit.
+#[derive(Clone, Default)]
+struct FriendLivenessStats {
+    /// Ticks still to wait before the next retransmit attempt, counted down once per
+    /// `timer_tick`. Set to `backoff_ticks` every time we actually retransmit.
+    ticks_until_retransmit: u64,
+    /// Current backoff step in ticks; doubled (capped at `MAX_RETRANSMIT_BACKOFF_TICKS`) on every
+    /// retransmit that goes unanswered, reset to 0 by `record_friend_progress`.
+    backoff_ticks: u64,
+    /// Consecutive `timer_tick`s this friend has had something outstanding to retransmit without
+    /// making progress. Reset to 0 by `record_friend_progress`.
+    missed_ticks: u32,
+    /// Whether we've already emitted a `FriendLiveness { is_online: false }` task for this
+    /// friend's current offline episode, so `timer_tick` reports the transition once rather than
+    /// on every subsequent tick.
+    reported_offline: bool,
+}
+
+/// A friend crossing the online/offline liveness boundary, as reported to the AppManager.
+/// `timer_tick` emits `is_online: false` once a friend has gone `MAX_MISSED_TICKS_BEFORE_OFFLINE`
+/// ticks without progress; `record_friend_progress` emits `is_online: true` the next time that
+/// friend is heard from again.
+pub struct FriendLivenessUpdate {
+    pub friend_public_key: PublicKey,
+    pub is_online: bool,
+}
+
+/// Per-friend cap on "unestablished" token channel slots - zero balance in both directions and no
+/// completed move-token exchange yet - an untrusted friend may have open at once. A friend we've
+/// configured with nonzero trust (see `MutableFunderHandler::is_trusted_friend`) is exempt
+/// entirely, same as a slot that's already carrying a nonzero balance for reset.
+const MAX_UNESTABLISHED_SLOTS_PER_FRIEND: usize = 4;
+/// Global cap on unestablished slots summed across every untrusted friend, independent of the
+/// per-friend cap - bounds the total resource cost of the DoS even if it's spread thin across many
+/// distinct peers instead of concentrated on one.
+const MAX_UNESTABLISHED_SLOTS_GLOBAL: usize = 256;
+
+/// Classification of a terminal failure reported for a request we originated, used by
+/// `retry_or_give_up` to decide whether spending another attempt is even worth it. This protocol's
+/// `FailureSendFunds` carries no reason code of its own (unlike, say, a Lightning onion failure
+/// message), so the classification is necessarily based on context available to us rather than on
+/// anything the reporting node put in the failure message itself - see `classify_send_failure`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RetryableSendFailure {
+    /// Route-finding for a retry came up empty. Transient in principle (a new friend or freed-up
+    /// capacity could open a route later), but there's nothing to do about it right now.
+    /// `classify_send_failure` also returns it when the reporting key is not on the route we sent.
+    RouteNotFound,
+    /// The reporting node is a known, consistent friend that simply couldn't or wouldn't forward
+    /// this particular request - our catch-all for "nothing obviously structural is wrong", since
+    /// this protocol has no richer rejection reason to go on. Worth retrying over a route that
+    /// avoids it.
+    RemoteRejected,
+    /// Our channel with the reporting node is itself mid reset-negotiation. Retrying immediately
+    /// would very likely just run into the same wall again before the reset completes, so this is
+    /// treated as non-retryable for this failure, the same as `Permanent`.
+    ChannelInconsistent,
+    /// Not retryable at all. `classify_send_failure` never returns this: a reporting node that is
+    /// not a friend of ours is classified by its place on the route instead.
+    Permanent,
+}
+
+/// Outcome of a single retry decision, as made by `retry_or_give_up`.
+enum RetryOutcome {
+    /// A further attempt was queued; nothing to report yet.
+    Retrying,
+    /// The attempt budget, the route-finding layer, or the failure's own classification ruled out
+    /// retrying. The caller decides how to report this `payment_id` as failed (directly to the
+    /// crypter, or via its shard group).
+    GaveUp {
+        payment_id: Uid,
+        reporting_public_key: PublicKey,
+        reason: RetryableSendFailure,
+    },
+}
 
 
 // Approximate maximum size of a MOVE_TOKEN message.
@@ -57,6 +353,86 @@ pub enum IncomingFriendFunds {
 pub enum HandleFriendError {
 }
 
+/// Mutations and tasks computed by an inner friend-message handler (`compute_move_token_error_effects`,
+/// `compute_inconsistency_error_effects`, `cancel_local_pending_requests`) but not yet applied.
+/// Collecting them here rather than calling `apply_mutation`/`add_task` inline as they're decided
+/// means a handler that fails partway through (for instance `cancel_local_pending_requests`
+/// signing a failure message for one pending request before erroring out on the next) never
+/// leaves some of its mutations committed and others missing - see `commit_friend_effects`, the
+/// only place a `DeferredFriendEffects` is actually applied.
+#[derive(Default)]
+struct DeferredFriendEffects {
+    mutations: Vec<FunderMutation>,
+    tasks: Vec<FunderTask>,
+}
+
+/// Which way a request is flowing through this node, as reported by
+/// `MutableFunderHandler::introspect_request`.
+pub enum RequestDirection {
+    /// We are this request's origin; it entered the network here rather than from a friend.
+    Originated,
+    /// The request arrived from `entry_friend` and is (or was) forwarded onward.
+    Forwarded { entry_friend: PublicKey },
+}
+
+/// Per-request detail exposed by `MutableFunderHandler::introspect_request`, analogous to
+/// rust-lightning's `InboundHTLCDetails`/`OutboundHTLCDetails`: enough for an operator to see where
+/// a request came from, where it was sent, and how much liquidity it has tied up.
+pub struct RequestIntrospection {
+    pub direction: RequestDirection,
+    /// Friend we ourselves forwarded this request onward to, if we have forwarded it and it
+    /// hasn't settled yet. `None` if we are the request's destination.
+    pub forward_friend: Option<PublicKey>,
+    /// `dest_payment` of the forwarded copy of this request - the credit currently frozen on our
+    /// outgoing hop for it. 0 if `forward_friend` is `None`.
+    pub frozen_credit: u128,
+    /// Ticks (per `cache.tick`) elapsed since we forwarded this request. 0 if `forward_friend` is
+    /// `None`.
+    pub age_ticks: u64,
+}
+
+/// One onion-encrypted layer of a blinded route's downstream segment: readable only by the friend
+/// it addresses, revealing just its own immediate successor (`None` for the final, destination
+/// hop) plus the still-sealed remainder for every hop after that. Built by a request's originator
+/// in `MutableFunderHandler::build_blinded_route`; peeled one layer at a time by
+/// `MutableFunderHandler::peel_blinded_hop` as the request travels from friend to friend. Adapted
+/// from rust-lightning's `BlindedPath`.
+#[derive(Clone)]
+pub struct BlindedRoute {
+    ciphertext: Vec<u8>,
+}
+
+/// Result of peeling our own layer off a `BlindedRoute` via `MutableFunderHandler::peel_blinded_hop`.
+pub struct BlindedHop {
+    /// Who to forward the request (and `rest`) to next. `None` marks us as the request's final
+    /// destination - nothing is left to peel.
+    pub opt_next_public_key: Option<PublicKey>,
+    /// Onion-encrypted payload for every hop after this one, to attach to the forwarded request
+    /// in place of the `BlindedRoute` we just peeled.
+    pub rest: BlindedRoute,
+    /// Secret recovered while peeling, remembered via `MutableFunderHandler::record_hop_secret` in
+    /// case *we* end up being the hop that reports a failure for this request - see
+    /// `MutableFunderHandler::seal_failure_report`.
+    pub hop_secret: HopSecret,
+}
+
+/// Per-hop secret recovered while peeling one layer of a `BlindedRoute`; opaque outside the
+/// blinding layer (`cache.route_blinder`). Sealing a failure under it produces something only the
+/// party that originally sealed that layer - the request's originator - can open again.
+#[derive(Clone)]
+pub struct HopSecret {
+    bytes: Vec<u8>,
+}
+
+/// A failure report sealed under a `HopSecret`, carried back in `FailureSendFunds` in place of (or
+/// alongside, for this snapshot's unmodified wire struct - see the note above
+/// `MutableFunderHandler::forward_or_punt_blinded`) a plaintext reporting identity, so a hop merely
+/// relaying it towards the originator learns nothing beyond "a failure happened somewhere
+/// downstream" - mirroring BOLT4's onion-wrapped error messages.
+#[derive(Clone)]
+pub struct BlindedFailureReport {
+    ciphertext: Vec<u8>,
+}
 
 #[allow(unused)]
 impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
@@ -70,9 +446,23 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
     /// Returns the public key of a friend together with the channel_index of a
     /// token channel. If we are the origin of this request, the function return None.
     ///
-    /// TODO: We need to change this search to be O(1) in the future. Possibly by maintaining a map
-    /// between request_id and (friend_public_key, friend).
-    fn find_request_origin(&self, request_id: &Uid) -> Option<&PublicKey> {
+    /// Backed by `cache.request_origin_index`, maintained as an O(1) map from request_id to
+    /// `(friend_public_key, channel_index)` at the points where a request enters that state
+    /// (`handle_request_send_funds`) and leaves it (`handle_response_send_funds`,
+    /// `handle_failure_send_funds`, `timer_tick`, `cancel_local_pending_requests`), rather than by
+    /// scanning every friend's pending_remote_requests on every lookup. `find_request_origin_scan`
+    /// below is kept only as a defensive fallback for a request_id the index doesn't have an entry
+    /// for (e.g. one that predates the index being populated).
+    fn find_request_origin(&self, request_id: &Uid) -> Option<(PublicKey, u16)> {
+        self.cache.request_origin_index.get(request_id).cloned()
+            .or_else(|| self.find_request_origin_scan(request_id))
+    }
+
+    /// Linear fallback for `find_request_origin`. This snapshot's `FriendState` only exposes a
+    /// single `directional.token_channel` per friend (no addressable slots), so unlike the index
+    /// (which records the real channel_index a request arrived on), this fallback can only ever
+    /// report channel_index 0.
+    fn find_request_origin_scan(&self, request_id: &Uid) -> Option<(PublicKey, u16)> {
         for (friend_public_key, friend) in self.state.get_friends() {
             if friend.directional
                 .token_channel
@@ -80,7 +470,116 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                 .pending_requests
                 .pending_remote_requests
                 .contains_key(request_id) {
-                    return Some(friend_public_key)
+                    return Some((friend_public_key.clone(), 0))
+            }
+        }
+        None
+    }
+
+    /// Per-request introspection, as reported to e.g. an AppManager. `None` if `request_id` is not
+    /// currently in flight anywhere we can see.
+    pub fn introspect_request(&self, request_id: &Uid) -> Option<RequestIntrospection> {
+        let opt_entry_friend = self.find_request_origin(request_id).map(|(friend_public_key, _)| friend_public_key);
+        let opt_forward = self.find_forward_friend(request_id);
+
+        if opt_entry_friend.is_none() && opt_forward.is_none() {
+            return None;
+        }
+
+        let direction = match opt_entry_friend {
+            Some(entry_friend) => RequestDirection::Forwarded { entry_friend },
+            None => RequestDirection::Originated,
+        };
+
+        let (forward_friend, frozen_credit, age_ticks) = match opt_forward {
+            Some((friend_public_key, pending)) => (
+                Some(friend_public_key),
+                pending.dest_payment,
+                self.cache.tick.saturating_sub(pending.created_at),
+            ),
+            None => (None, 0, 0),
+        };
+
+        Some(RequestIntrospection {
+            direction,
+            forward_friend,
+            frozen_credit,
+            age_ticks,
+        })
+    }
+
+    /// Record the origin of a freshly-admitted remote request in `cache.request_origin_index`, so
+    /// later `find_request_origin` calls for it are O(1). Paired with `release_request_origin`, at
+    /// the same points `record_friend_frozen_credit`/`release_friend_frozen_credit` are.
+    fn record_request_origin(&mut self, request_id: Uid, friend_public_key: PublicKey, channel_index: u16) {
+        self.cache.request_origin_index.insert(request_id, (friend_public_key, channel_index));
+    }
+
+    /// Drop a resolved request's entry from `cache.request_origin_index`.
+    fn release_request_origin(&mut self, request_id: &Uid) {
+        self.cache.request_origin_index.remove(request_id);
+    }
+
+    /// Remember the per-hop secret `forward_or_punt_blinded` recovered while peeling a blinded
+    /// route for `request_id`, so `create_failure_message` can seal a failure under it later if we
+    /// end up being the hop that reports one.
+    fn record_hop_secret(&mut self, request_id: Uid, hop_secret: HopSecret) {
+        self.cache.hop_secrets.insert(request_id, hop_secret);
+    }
+
+    /// Take (and forget) the hop secret recorded for `request_id`, if any. A `Some` result also
+    /// doubles as "we reached this request as a blinded-forwarding hop, not as its origin" -
+    /// `handle_response_send_funds`/`handle_failure_send_funds` use that to skip scoring a route
+    /// they never actually saw.
+    fn take_hop_secret(&mut self, request_id: &Uid) -> Option<HopSecret> {
+        self.cache.hop_secrets.remove(request_id)
+    }
+
+    /// Build the blinded onion covering `route`'s downstream segment (every hop after us), so each
+    /// forwarding friend can recover only its own successor - never the full path or final
+    /// destination. Actual per-hop key agreement and AEAD sealing belongs to the blinding layer,
+    /// not part of this snapshot; this only defines the extension point
+    /// `initiate_blinded_payment` calls through to.
+    fn build_blinded_route(&self, route: &FriendsRoute) -> BlindedRoute {
+        self.cache.route_blinder.blind(route)
+    }
+
+    /// Peel our own layer off `blinded`. `None` if it can't be decrypted by us at all - not
+    /// addressed to us, or malformed - which `forward_or_punt_blinded` treats as a DoS-style
+    /// rejection, same as a freeze-guard failure.
+    fn peel_blinded_hop(&self, blinded: &BlindedRoute) -> Option<BlindedHop> {
+        self.cache.route_blinder.peel(blinded)
+    }
+
+    /// Seal `reporting_public_key` into a fresh `BlindedFailureReport` under `hop_secret` - the one
+    /// onion layer a reporting hop applies before sending a failure back. Every hop the failure
+    /// then passes back through on its way to the originator adds no further wrapping of its own;
+    /// it only relays the already-sealed blob unchanged, exactly as it relayed the still-encrypted
+    /// `rest` of the route forward.
+    fn seal_failure_report(&self, hop_secret: &HopSecret, reporting_public_key: PublicKey) -> BlindedFailureReport {
+        self.cache.route_blinder.seal_failure(hop_secret, reporting_public_key)
+    }
+
+    /// Peel a `BlindedFailureReport` built from one of our own blinded routes, recovering which hop
+    /// reported the failure. Only a request's originator can do this, since only it ever learned
+    /// every hop's secret (by constructing them all in `build_blinded_route`); an intermediate
+    /// forwarder holding the same report has no way to open it and must not need to.
+    fn open_failure_report(&self, request_id: &Uid, report: &BlindedFailureReport) -> Option<PublicKey> {
+        self.cache.route_blinder.open_failure(request_id, report)
+    }
+
+    /// Find the friend (if any) we ourselves forwarded `request_id` onward to, together with our
+    /// record of that forwarded request. Used by `introspect_request` to report the forward hop,
+    /// frozen credit, and in-flight age of a request.
+    fn find_forward_friend(&self, request_id: &Uid) -> Option<(PublicKey, PendingFriendRequest)> {
+        for (friend_public_key, friend) in self.state.get_friends() {
+            if let Some(pending) = friend.directional
+                .token_channel
+                .state()
+                .pending_requests
+                .pending_local_requests
+                .get(request_id) {
+                    return Some((friend_public_key.clone(), pending.clone()))
             }
         }
         None
@@ -88,21 +587,30 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
 
     /// Create a (signed) failure message for a given request_id.
     /// We are the reporting_public_key for this failure message.
+    ///
+    /// If `forward_or_punt_blinded` recorded a `HopSecret` for this request (i.e. we reached it as
+    /// a blinded-forwarding hop rather than as its origin), the failure is additionally sealed
+    /// into a `BlindedFailureReport` under that secret, so a hop further up the route that merely
+    /// relays it back learns nothing beyond "a failure happened somewhere downstream" - exactly as
+    /// it learned nothing about the rest of the route when it forwarded the request in the first
+    /// place.
     #[async]
-    fn create_failure_message(mut self, pending_local_request: PendingFriendRequest) 
+    fn create_failure_message(mut self, pending_local_request: PendingFriendRequest)
         -> Result<(Self, FailureSendFunds), HandleFriendError> {
 
         let rand_nonce = RandValue::new(&*self.rng);
         let local_public_key = self.state.get_local_public_key().clone();
+        let opt_hop_secret = self.take_hop_secret(&pending_local_request.request_id);
 
         let failure_send_funds = FailureSendFunds {
-            request_id: pending_local_request.request_id,
+            request_id: pending_local_request.request_id.clone(),
             reporting_public_key: local_public_key.clone(),
+            opt_blinded_report: None,
             rand_nonce,
             signature: Signature::zero(),
         };
         // TODO: Add default() implementation for Signature
-        
+
         let mut failure_signature_buffer = create_failure_signature_buffer(
                                             &failure_send_funds,
                                             &pending_local_request);
@@ -110,22 +618,33 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
         let signature = await!(self.security_module_client.request_signature(failure_signature_buffer))
             .expect("Failed to create a signature!");
 
+        let opt_blinded_report = opt_hop_secret.map(|hop_secret|
+            self.seal_failure_report(&hop_secret, local_public_key.clone()));
+
         Ok((self, FailureSendFunds {
             request_id: pending_local_request.request_id,
             reporting_public_key: local_public_key,
+            opt_blinded_report,
             rand_nonce,
             signature,
         }))
     }
 
 
+    /// Compute (without applying) the mutations needed to cancel every one of this friend's
+    /// `pending_local_requests` - one `FailureSendFunds` queued toward each request's origin.
+    /// Signing still happens here (it's an external call, not a `self.state` mutation, so there's
+    /// nothing unsafe about doing it eagerly while we're only computing effects); what's deferred
+    /// is the `apply_mutation` call that used to happen once per loop iteration, which is what let
+    /// a signature failure on request N leave requests before it already cancelled and the rest
+    /// untouched. See `DeferredFriendEffects`.
     #[async]
-    fn cancel_local_pending_requests(mut self, 
-                                     friend_public_key: PublicKey) -> Result<Self, HandleFriendError> {
+    fn cancel_local_pending_requests(mut self,
+                                     friend_public_key: PublicKey) -> Result<(Self, DeferredFriendEffects), HandleFriendError> {
 
         let friend = self.get_friend(&friend_public_key).unwrap();
 
-        // Mark all pending requests to this friend as errors.  
+        // Mark all pending requests to this friend as errors.
         // As the token channel is being reset, we can be sure we will never obtain a response
         // for those requests.
         let pending_local_requests = friend.directional
@@ -137,30 +656,289 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
 
         let local_public_key = self.state.get_local_public_key().clone();
         let mut fself = self;
+        let mut effects = DeferredFriendEffects::default();
         // Prepare a list of all remote requests that we need to cancel:
         for (local_request_id, pending_local_request) in pending_local_requests {
             let opt_origin_public_key = fself.find_request_origin(&local_request_id);
-            let origin_public_key = match opt_origin_public_key {
-                Some(origin_public_key) => origin_public_key,
+            let (origin_public_key, _channel_index) = match opt_origin_public_key {
+                Some(origin) => origin,
                 None => continue,
             };
+            fself.release_friend_frozen_credit(&origin_public_key, pending_local_request.dest_payment);
+            fself.release_request_origin(&local_request_id);
 
             let (new_fself, failure_send_funds) = await!(fself.create_failure_message(pending_local_request))?;
             fself = new_fself;
 
             let failure_op = FriendTcOp::FailureSendFunds(failure_send_funds);
             let friend_mutation = FriendMutation::PushBackPendingOperation(failure_op);
-            let messenger_mutation = FunderMutation::FriendMutation((origin_public_key.clone(), friend_mutation));
-            fself.apply_mutation(messenger_mutation);
+            effects.mutations.push(FunderMutation::FriendMutation((origin_public_key, friend_mutation)));
         }
-        Ok(fself)
+        Ok((fself, effects))
    }
 
 
+    /// Periodic entry point, meant to be driven roughly once per timer period by the embedding
+    /// executor (see `src/timer.rs`'s `TimerTick`). Scans every friend's `pending_local_requests`
+    /// for entries older than `DEFAULT_PENDING_REQUEST_TIMEOUT_TICKS`, and for each: synthesizes a
+    /// local failure (with us as `reporting_public_key`, exactly as `create_failure_message` does
+    /// for a channel reset), releases its frozen credit, and pushes the resulting
+    /// `FailureSendFunds` toward the request's origin exactly as `handle_failure_send_funds` does.
+    ///
+    /// This snapshot has no mutation to retract a single `pending_local_requests` entry outside of
+    /// a full channel reset, so the expired request still shows up on the next scan; expired
+    /// request_ids are instead remembered in `cache.expired_requests` so a late genuine response or
+    /// failure for one of them (see the guards at the top of `handle_response_send_funds` /
+    /// `handle_failure_send_funds`) is dropped instead of being reported to the crypter twice.
+    ///
+    /// Also drives per-friend retransmission and liveness tracking (see `tick_friend_liveness`),
+    /// so a friend that has gone silent after we handed it the token - rather than one that keeps
+    /// sending us duplicates, which `handle_move_token_success`'s `RetransmitOutgoing` arm already
+    /// covers - still gets its outgoing move token (or pending inconsistency reset) resent.
+    #[async]
+    pub fn timer_tick(mut self) -> Result<Self, HandleFriendError> {
+        self.cache.tick += 1;
+        let now = self.cache.tick;
+
+        let friend_public_keys: Vec<PublicKey> = self.state.get_friends().keys().cloned().collect();
+
+        let mut fself = self;
+        for friend_public_key in friend_public_keys {
+            let pending_local_requests = match fself.get_friend(&friend_public_key) {
+                Some(friend) => friend.directional
+                    .token_channel
+                    .state()
+                    .pending_requests
+                    .pending_local_requests
+                    .clone(),
+                None => continue,
+            };
+
+            for (local_request_id, pending_local_request) in pending_local_requests {
+                if fself.cache.expired_requests.contains(&local_request_id) {
+                    continue;
+                }
+                let age = now.saturating_sub(pending_local_request.created_at);
+                if age < DEFAULT_PENDING_REQUEST_TIMEOUT_TICKS {
+                    continue;
+                }
+
+                let opt_origin_public_key = fself.find_request_origin(&local_request_id);
+                let (origin_public_key, _channel_index) = match opt_origin_public_key {
+                    Some(origin) => origin,
+                    None => continue,
+                };
+
+                fself.cache.expired_requests.insert(local_request_id.clone());
+                fself.cache.freeze_guard.sub_frozen_credit(&pending_local_request);
+                fself.release_friend_frozen_credit(&origin_public_key, pending_local_request.dest_payment);
+                fself.release_request_origin(&local_request_id);
+
+                let (new_fself, failure_send_funds) = await!(fself.create_failure_message(pending_local_request))?;
+                fself = new_fself;
+
+                let failure_op = FriendTcOp::FailureSendFunds(failure_send_funds);
+                let friend_mutation = FriendMutation::PushBackPendingOperation(failure_op);
+                let messenger_mutation = FunderMutation::FriendMutation((origin_public_key, friend_mutation));
+                fself.apply_mutation(messenger_mutation);
+            }
+
+            fself.tick_friend_liveness(&friend_public_key);
+        }
+        Ok(fself)
+    }
+
+    /// `timer_tick`'s per-friend retransmission and liveness sweep. Walks every one of
+    /// `friend_public_key`'s token channel slots (`0..local_max_channels`) looking for an
+    /// unacknowledged outgoing move token or a sent-but-unacked inconsistency reset; resends
+    /// whichever it finds once the friend's exponential backoff timer reaches 0, and tracks
+    /// consecutive ticks without progress so a friend that never answers eventually gets reported
+    /// offline.
+    fn tick_friend_liveness(&mut self, friend_public_key: &PublicKey) {
+        let local_max_channels = match self.get_friend(friend_public_key) {
+            Some(friend) => friend.local_max_channels,
+            None => return,
+        };
+
+        let mut has_outstanding = false;
+        for channel_index in 0..local_max_channels {
+            let tc_slot = self.get_token_channel_slot(friend_public_key, channel_index);
+            let opt_outgoing_move_token = tc_slot.directional.get_outgoing_move_token();
+            let inconsistency_sent = match tc_slot.inconsistency_status.outgoing {
+                OutgoingInconsistency::Sent => true,
+                _ => false,
+            };
+
+            if opt_outgoing_move_token.is_none() && !inconsistency_sent {
+                continue;
+            }
+            has_outstanding = true;
+
+            let stats = self.cache.friend_liveness.entry(friend_public_key.clone()).or_default();
+            if stats.ticks_until_retransmit > 0 {
+                stats.ticks_until_retransmit -= 1;
+                continue;
+            }
+
+            stats.backoff_ticks = if stats.backoff_ticks == 0 {
+                INITIAL_RETRANSMIT_BACKOFF_TICKS
+            } else {
+                (stats.backoff_ticks * 2).min(MAX_RETRANSMIT_BACKOFF_TICKS)
+            };
+            stats.ticks_until_retransmit = stats.backoff_ticks;
+
+            if let Some(outgoing_move_token) = opt_outgoing_move_token {
+                self.add_task(
+                    FunderTask::FriendFunds(
+                        FriendFunds::MoveToken(outgoing_move_token)));
+            }
+            if inconsistency_sent {
+                self.resend_outgoing_inconsistency(friend_public_key, channel_index);
+            }
+        }
+
+        if !has_outstanding {
+            return;
+        }
+
+        let stats = self.cache.friend_liveness.entry(friend_public_key.clone()).or_default();
+        stats.missed_ticks = stats.missed_ticks.saturating_add(1);
+        if stats.missed_ticks >= MAX_MISSED_TICKS_BEFORE_OFFLINE && !stats.reported_offline {
+            stats.reported_offline = true;
+            self.add_task(
+                FunderTask::AppManagerFunds(
+                    AppManagerFunds::FriendLiveness(FriendLivenessUpdate {
+                        friend_public_key: friend_public_key.clone(),
+                        is_online: false,
+                    })));
+        }
+    }
+
+    /// Resend our current reset terms for `channel_index` as a fresh `FriendInconsistencyError`,
+    /// without waiting for (or requiring) a new inconsistency message from the remote side to
+    /// trigger it - `compute_inconsistency_error_effects` builds the same message in response to one.
+    /// `opt_ack` reflects whatever reset terms we've already received from the remote for this
+    /// channel, if any, exactly as it would if this were computed on receipt of a new message.
+    fn resend_outgoing_inconsistency(&mut self, friend_public_key: &PublicKey, channel_index: u16) {
+        let tc_slot = self.get_token_channel_slot(friend_public_key, channel_index);
+        let reset_token = tc_slot.directional.calc_channel_reset_token(channel_index);
+        let balance_for_reset = tc_slot.directional.balance_for_reset();
+        let opt_ack = match &tc_slot.inconsistency_status.incoming {
+            IncomingInconsistency::Incoming(reset_terms) => Some(reset_terms.current_token.clone()),
+            IncomingInconsistency::Empty => None,
+        };
+
+        let inconsistency_error = FriendInconsistencyError {
+            opt_ack,
+            token_channel_index: channel_index,
+            current_token: reset_token,
+            balance_for_reset,
+        };
+
+        self.add_task(
+            FunderTask::FriendFunds(
+                FriendFunds::InconsistencyError(inconsistency_error)));
+    }
+
+    /// Record that `friend_public_key` made progress (we just applied a non-duplicate move token
+    /// from it in `handle_move_token_success`): clears its retransmit backoff and missed-tick
+    /// count, and - if it had previously been reported offline - returns a `FriendLivenessUpdate`
+    /// reporting it back online.
+    fn record_friend_progress(&mut self, friend_public_key: &PublicKey) -> Option<FriendLivenessUpdate> {
+        let stats = self.cache.friend_liveness.entry(friend_public_key.clone()).or_default();
+        stats.ticks_until_retransmit = 0;
+        stats.backoff_ticks = 0;
+        stats.missed_ticks = 0;
+
+        if stats.reported_offline {
+            stats.reported_offline = false;
+            Some(FriendLivenessUpdate {
+                friend_public_key: friend_public_key.clone(),
+                is_online: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// A friend we've configured with nonzero trust - see `build_freeze_link`'s use of the same
+    /// `get_trust()` - is exempt from the unestablished-slot cap entirely: we've already vouched
+    /// for it, so refusing it a slot for resource-limiting reasons would only hurt a legitimate
+    /// peer, not an attacker.
+    fn is_trusted_friend(&self, friend_public_key: &PublicKey) -> bool {
+        self.get_friend(friend_public_key)
+            .map(|friend| friend.get_trust() > BigUint::from(0u32))
+            .unwrap_or(false)
+    }
+
+    /// Decide whether `handle_move_token` should allocate state for a not-yet-seen
+    /// `channel_index` on `friend_public_key`, enforcing `MAX_UNESTABLISHED_SLOTS_PER_FRIEND` and
+    /// `MAX_UNESTABLISHED_SLOTS_GLOBAL` against untrusted friends. A slot this function has already
+    /// admitted (whether since established via `mark_slot_established` or still sitting
+    /// unestablished) is always admitted again - the cap only ever blocks the first message for an
+    /// index we haven't seen before.
+    fn admit_new_slot(&mut self, friend_public_key: &PublicKey, channel_index: u16) -> bool {
+        if self.cache.established_slots.contains(&(friend_public_key.clone(), channel_index)) {
+            return true;
+        }
+        let already_known = self.cache.unestablished_slots.get(friend_public_key)
+            .map_or(false, |slots| slots.contains(&channel_index));
+        if already_known {
+            return true;
+        }
+
+        if self.is_trusted_friend(friend_public_key) {
+            self.cache.unestablished_slots.entry(friend_public_key.clone()).or_default().insert(channel_index);
+            return true;
+        }
+
+        let per_friend_count = self.cache.unestablished_slots.get(friend_public_key).map_or(0, |s| s.len());
+        let global_count: usize = self.cache.unestablished_slots.values().map(|s| s.len()).sum();
+        if per_friend_count >= MAX_UNESTABLISHED_SLOTS_PER_FRIEND || global_count >= MAX_UNESTABLISHED_SLOTS_GLOBAL {
+            return false;
+        }
+
+        self.cache.unestablished_slots.entry(friend_public_key.clone()).or_default().insert(channel_index);
+        true
+    }
+
+    /// Promote `channel_index` on `friend_public_key` out of unestablished-slot accounting once it
+    /// completes a move-token exchange (called from `handle_move_token_success`'s `Received` arm) -
+    /// from then on it's exempt from `admit_new_slot`'s cap even if its balance later returns to
+    /// zero.
+    fn mark_slot_established(&mut self, friend_public_key: &PublicKey, channel_index: u16) {
+        if let Some(slots) = self.cache.unestablished_slots.get_mut(friend_public_key) {
+            slots.remove(&channel_index);
+        }
+        self.cache.established_slots.insert((friend_public_key.clone(), channel_index));
+    }
+
+    /// Apply a `DeferredFriendEffects` bundle atomically: every mutation it collected, then every
+    /// task. The only caller-visible guarantee this buys is ordering/all-or-nothing commit of
+    /// what's already been decided - callers that can still fail (anything returning
+    /// `Result<_, HandleFriendError>`) must finish computing their `DeferredFriendEffects` and
+    /// return `Ok` before calling this, so an `Err` never reaches here with only some of the
+    /// bundle applied.
+    fn commit_friend_effects(&mut self, effects: DeferredFriendEffects) {
+        for mutation in effects.mutations {
+            self.apply_mutation(mutation);
+        }
+        for task in effects.tasks {
+            self.add_task(task);
+        }
+    }
+
     /// Check if channel reset is required (Remove side used the RESET token)
     /// If so, reset the channel.
+    ///
+    /// The reset itself (cancelling every pending local request, then applying `RemoteReset`) is
+    /// committed here, immediately, rather than handed further up to `handle_move_token`: the
+    /// token channel processing that follows a reset in the same incoming move token needs to see
+    /// the already-reset state. What this function fixes is the reset no longer being able to
+    /// leave only *some* of `cancel_local_pending_requests`' cancellations applied if a later one
+    /// in the same batch fails - see `DeferredFriendEffects`.
     #[async]
-    fn check_reset_channel(mut self, 
+    fn check_reset_channel(mut self,
                            friend_public_key: PublicKey,
                            new_token: ChannelToken) -> Result<Self, HandleFriendError> {
         // Check if incoming message is an attempt to reset channel.
@@ -171,12 +949,12 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
 
         if new_token == reset_token {
             // This is a reset message. We reset the token channel:
-            let mut fself = await!(self.cancel_local_pending_requests(
+            let (mut fself, mut effects) = await!(self.cancel_local_pending_requests(
                 friend_public_key.clone()))?;
 
             let friend_mutation = FriendMutation::RemoteReset;
-            let messenger_mutation = FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
-            fself.apply_mutation(messenger_mutation);
+            effects.mutations.push(FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation)));
+            fself.commit_friend_effects(effects);
 
             Ok(fself)
         } else {
@@ -202,24 +980,365 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
         Ok(fself)
     }
 
-    /// Forward a request message to the relevant friend and token channel.
-    fn forward_request(&mut self, mut request_send_funds: RequestSendFunds) {
-        let index = request_send_funds.route.pk_index(self.state.get_local_public_key())
-            .expect("We are not present in the route!");
-        let prev_index = index.checked_sub(1).expect("We are the originator of this request");
-        let next_index = index.checked_add(1).expect("Index out of range");
-        
-        let prev_pk = request_send_funds.route.pk_by_index(prev_index)
-            .expect("Could not obtain previous public key");
-        let next_pk = request_send_funds.route.pk_by_index(prev_index)
-            .expect("Could not obtain next public key");
+    /// Ask the route-finding layer for a route to `pending_request`'s destination that avoids
+    /// every hop in `blacklist`. Actual path computation belongs to whatever component maintains
+    /// our view of the network graph (e.g. an index client); that component isn't part of this
+    /// snapshot, so this only defines the extension point the retry logic below calls through to.
+    ///
+    /// Passes `edge_preference` through as the candidate-ranking callback, so the route finder's
+    /// choice among equally-viable paths is biased towards edges `RouteScorer` has learned are
+    /// reliable, rather than picking among candidates blind to that history.
+    fn find_alternate_route(&self, pending_request: &PendingFriendRequest,
+                            blacklist: &HashSet<PublicKey>) -> Option<FriendsRoute> {
+        self.cache.route_finder.find_alternate_route(pending_request, blacklist,
+            &|prev_pk, next_pk| self.edge_preference(prev_pk, next_pk))
+    }
+
+    /// Ask the route-finding layer for a fresh route to `destination`, avoiding every hop in
+    /// `blacklist`. Used to place a brand new payment (or shard of one), as opposed to
+    /// `find_alternate_route`, which retries an existing one.
+    ///
+    /// See `find_alternate_route` on why `edge_preference` is passed through as the ranking
+    /// callback.
+    fn find_route(&self, destination: &PublicKey, blacklist: &HashSet<PublicKey>) -> Option<FriendsRoute> {
+        self.cache.route_finder.find_route(destination, blacklist,
+            &|prev_pk, next_pk| self.edge_preference(prev_pk, next_pk))
+    }
+
+    /// Approximate, floating-point version of `forward_request`'s usable_ratio calculation, used
+    /// only to bias route preference - not precise enough (nor does it need to be) for the actual
+    /// freeze link put on the wire, which still goes through the exact `BigUint` arithmetic in
+    /// `forward_request`. Returns 0.0 if either hop isn't a direct friend of ours.
+    fn usable_ratio_estimate(&self, prev_pk: &PublicKey, next_pk: &PublicKey) -> f64 {
+        let prev_friend = match self.state.get_friends().get(prev_pk) {
+            Some(friend) => friend,
+            None => return 0.0,
+        };
+        let next_friend = match self.state.get_friends().get(next_pk) {
+            Some(friend) => friend,
+            None => return 0.0,
+        };
 
-        let prev_friend = self.state.get_friends().get(&prev_pk)
+        let total_trust = self.state.get_total_trust();
+        let prev_trust = prev_friend.get_trust();
+        let forward_trust = next_friend.get_trust();
+
+        let denom = (total_trust - &prev_trust).to_f64().unwrap_or(0.0);
+        if denom <= 0.0 {
+            return 0.0;
+        }
+        forward_trust.to_f64().unwrap_or(0.0) / denom
+    }
+
+    /// Combined preference score for forwarding from `prev_pk` to `next_pk`: the historical success
+    /// probability of that edge (`RouteScorer`) times its usable_ratio (trust-derived capacity
+    /// share). Exposed so route selection (`find_route`/`find_alternate_route`, via the
+    /// route-finding layer) can bias towards edges that are both reliable and have room to push
+    /// the payment through.
+    pub fn edge_preference(&self, prev_pk: &PublicKey, next_pk: &PublicKey) -> f64 {
+        let probability = self.cache.route_scorer.estimated_probability(
+            &(prev_pk.clone(), next_pk.clone()));
+        probability * self.usable_ratio_estimate(prev_pk, next_pk)
+    }
+
+    /// Queue a freshly (re)issued request to its first hop. Unlike `forward_request`, this is
+    /// called on the route's origin: there is no previous hop to add a freeze link for.
+    fn queue_first_hop(&mut self, request_send_funds: RequestSendFunds) {
+        let next_pk = request_send_funds.route.pk_by_index(1)
+            .expect("Route too short!")
+            .clone();
+        self.queue_to_friend(next_pk, request_send_funds);
+    }
+
+    /// Shared tail of `queue_first_hop`/`initiate_blinded_payment`: push the request onto
+    /// `next_pk`'s pending-request queue.
+    fn queue_to_friend(&mut self, next_pk: PublicKey, request_send_funds: RequestSendFunds) {
+        let friend_mutation = FriendMutation::PushBackPendingRequest(request_send_funds);
+        let messenger_mutation = FunderMutation::FriendMutation((next_pk, friend_mutation));
+        self.apply_mutation(messenger_mutation);
+    }
+
+    /// Decide whether a failure is worth spending a retry attempt on. This protocol's
+    /// `FailureSendFunds` carries no reason code of its own, and `reporting_public_key` names
+    /// whichever hop along `route` actually failed - on a relayed, multi-hop payment that is
+    /// essentially never one of our own direct friends, only a node we know *of* via the route we
+    /// sent. So a missing `get_friend` lookup is the ordinary case, not a sign the failure is
+    /// unretryable; only when the reporting node happens to be a direct friend of ours *and* that
+    /// channel is itself mid-reset do we have a genuine structural reason not to bother. See
+    /// `RetryableSendFailure`.
+    fn classify_send_failure(&self, reporting_public_key: &PublicKey, route: &FriendsRoute) -> RetryableSendFailure {
+        if let Some(friend) = self.get_friend(reporting_public_key) {
+            for channel_index in 0..friend.local_max_channels {
+                let tc_slot = self.get_token_channel_slot(reporting_public_key, channel_index);
+                let incoming_clear = match tc_slot.inconsistency_status.incoming {
+                    IncomingInconsistency::Empty => true,
+                    _ => false,
+                };
+                let outgoing_clear = match tc_slot.inconsistency_status.outgoing {
+                    OutgoingInconsistency::Empty => true,
+                    _ => false,
+                };
+                if !incoming_clear || !outgoing_clear {
+                    return RetryableSendFailure::ChannelInconsistent;
+                }
+            }
+            return RetryableSendFailure::RemoteRejected;
+        }
+
+        // Not a direct friend - fall back to locating it on the route we actually sent. Whether
+        // it's an intermediate hop that couldn't forward or the destination itself declining,
+        // both are retryable over a route that avoids it; only a reporting key we can't even place
+        // on our own route gives us nothing to classify structurally.
+        match route.pk_index(reporting_public_key) {
+            Some(_) => RetryableSendFailure::RemoteRejected,
+            None => RetryableSendFailure::RouteNotFound,
+        }
+    }
+
+    /// Retry `retry.pending_request` over an alternate route after an attempt reported failure at
+    /// `reporting_public_key`, or give up if the failure's classification rules out retrying
+    /// outright, the attempt budget is exhausted, or no alternate route avoiding the accumulated
+    /// blacklist is available. `opt_shard_payment_id` carries the shard's payment_id forward onto
+    /// the new attempt's request_id if this attempt belongs to a `MultiPathPayment`; it is `None`
+    /// for an ordinary single-route payment.
+    fn retry_or_give_up(&mut self, mut retry: PaymentRetry, reporting_public_key: PublicKey,
+                        opt_shard_payment_id: Option<Uid>) -> RetryOutcome {
+        retry.blacklist.insert(reporting_public_key.clone());
+
+        let classification = self.classify_send_failure(&reporting_public_key, &retry.pending_request.route);
+        // A structural problem with the reporting node (it's gone, or mid channel-reset) means
+        // retrying right now can't possibly help - don't burn an attempt on it, give up directly.
+        let opt_route = match classification {
+            RetryableSendFailure::ChannelInconsistent | RetryableSendFailure::Permanent => None,
+            RetryableSendFailure::RouteNotFound | RetryableSendFailure::RemoteRejected => {
+                if retry.attempts_used >= MAX_PAYMENT_ATTEMPTS {
+                    None
+                } else {
+                    self.find_alternate_route(&retry.pending_request, &retry.blacklist)
+                }
+            },
+        };
+
+        match opt_route {
+            Some(route) => {
+                let new_request_id = Uid::new(&*self.rng);
+                let request_send_funds = build_retry_request(&retry.pending_request,
+                                                              new_request_id.clone(),
+                                                              route);
+                retry.attempts_used += 1;
+                if let Some(payment_id) = opt_shard_payment_id {
+                    self.cache.shard_to_payment.insert(new_request_id.clone(), payment_id);
+                }
+                self.cache.payment_retries.insert(new_request_id, retry);
+                self.queue_first_hop(request_send_funds);
+                RetryOutcome::Retrying
+            },
+            None => {
+                // If we got this far with a retryable classification, route-finding itself is what
+                // ran out; report that as the reason rather than the (stale) original classification.
+                let reason = match classification {
+                    RetryableSendFailure::ChannelInconsistent | RetryableSendFailure::Permanent => classification,
+                    RetryableSendFailure::RouteNotFound | RetryableSendFailure::RemoteRejected =>
+                        RetryableSendFailure::RouteNotFound,
+                };
+                RetryOutcome::GaveUp {
+                    payment_id: retry.payment_id,
+                    reporting_public_key,
+                    reason,
+                }
+            },
+        }
+    }
+
+    /// Record a shard's successful response against its `MultiPathPayment`. Once every shard has
+    /// responded, aggregate them into a single `ResponseReceived` for the crypter.
+    fn complete_multi_path_shard(&mut self, payment_id: Uid, response_send_funds: ResponseSendFunds) {
+        let is_finished = match self.cache.multi_path_payments.get_mut(&payment_id) {
+            Some(payment) => {
+                payment.pending_shards.remove(&response_send_funds.request_id);
+                payment.collected_responses.push(response_send_funds);
+                payment.pending_shards.is_empty()
+            },
+            None => return, // Payment already failed via another shard.
+        };
+
+        if !is_finished {
+            return;
+        }
+        let payment = self.cache.multi_path_payments.remove(&payment_id)
+            .expect("Just confirmed this payment_id is present above");
+        let processing_fee_collected = payment.collected_responses.iter()
+            .map(|response| response.processing_fee_collected)
+            .sum();
+        let response_content = payment.collected_responses.into_iter().next()
+            .expect("A finished MultiPathPayment has at least one shard")
+            .response_content;
+
+        self.messenger_tasks.push(
+            FunderTask::CrypterFunds(
+                CrypterFunds::ResponseReceived(ResponseReceived {
+                    request_id: payment_id,
+                    processing_fee_collected,
+                    response_content,
+                })
+            )
+        );
+    }
+
+    /// A shard of `payment_id` permanently failed after exhausting its own retries: fail the whole
+    /// payment with a single `FailureReceived`, and stop waiting on its other shards.
+    ///
+    /// The other, still-outstanding shards are not actively cancelled - this snapshot's token
+    /// channel protocol has no in-flight cancellation operation, only terminal Response/Failure -
+    /// so their frozen-credit reservations at each hop are released the ordinary way, whenever
+    /// their own response or failure eventually arrives; we simply no longer report their outcome
+    /// to the crypter once it has already been told the payment failed.
+    fn fail_multi_path_payment(&mut self, payment_id: Uid, reporting_public_key: PublicKey) {
+        if self.cache.multi_path_payments.remove(&payment_id).is_none() {
+            return; // Already failed (or completed) via another shard.
+        }
+        self.messenger_tasks.push(
+            FunderTask::CrypterFunds(
+                CrypterFunds::FailureReceived(FailureReceived {
+                    request_id: payment_id,
+                    reporting_public_key,
+                })
+            )
+        );
+    }
+
+    /// Split `dest_payment` into `num_shards` independent `RequestSendFunds`, each routed
+    /// separately (biased away from first hops already used by earlier shards, to spread the
+    /// payment across distinct token channels rather than funnel it all through one neighbor), and
+    /// register the result as a `MultiPathPayment` so `handle_response_send_funds` /
+    /// `handle_failure_send_funds` can track it to completion.
+    ///
+    /// Returns the logical payment_id that will eventually be reported back to the crypter via a
+    /// single `ResponseReceived`/`FailureReceived`, or `None` if not even one shard could be
+    /// routed.
+    pub fn initiate_multi_path_payment(&mut self, destination: PublicKey, invoice_id: InvoiceId,
+                                       dest_payment: u128, num_shards: u32) -> Option<Uid> {
+        let payment_id = Uid::new(&*self.rng);
+        let shares = split_payment(dest_payment, num_shards);
+
+        let mut pending_shards = HashSet::new();
+        let mut blacklist = HashSet::new();
+        for share in shares {
+            let route = match self.find_route(&destination, &blacklist) {
+                Some(route) => route,
+                None => continue,
+            };
+            if let Some(first_hop) = route.pk_by_index(1) {
+                blacklist.insert(first_hop.clone());
+            }
+
+            let request_id = Uid::new(&*self.rng);
+            let request_send_funds = RequestSendFunds {
+                request_id: request_id.clone(),
+                route,
+                dest_payment: share,
+                invoice_id: invoice_id.clone(),
+                freeze_links: Vec::new(),
+                opt_blinded_route: None,
+            };
+            self.cache.shard_to_payment.insert(request_id.clone(), payment_id.clone());
+            pending_shards.insert(request_id);
+            self.queue_first_hop(request_send_funds);
+        }
+
+        if pending_shards.is_empty() {
+            return None;
+        }
+
+        self.cache.multi_path_payments.insert(payment_id.clone(), MultiPathPayment {
+            pending_shards,
+            collected_responses: Vec::new(),
+        });
+        Some(payment_id)
+    }
+
+    /// Single-shot counterpart to `initiate_multi_path_payment`, sent over a blinded route so
+    /// `destination` is the only node besides ourselves who ever learns the full path - every
+    /// intermediate hop only recovers its own successor via `peel_blinded_hop`. Returns the
+    /// request_id the crypter should expect a `ResponseReceived`/`FailureReceived` for, or `None`
+    /// if no route to `destination` could be found.
+    pub fn initiate_blinded_payment(&mut self, destination: PublicKey, invoice_id: InvoiceId,
+                                    dest_payment: u128) -> Option<Uid> {
+        let route = self.find_route(&destination, &HashSet::new())?;
+        let blinded_route = self.build_blinded_route(&route);
+        let next_pk = route.pk_by_index(1).expect("Route too short!").clone();
+
+        let request_id = Uid::new(&*self.rng);
+        // Every hop recovers its own successor from `blinded_route` alone - the real route has no
+        // remaining reader once `next_pk` above is captured, so it's never written to the wire
+        // message. What's left is just the two endpoints, which the originator and destination
+        // already know about each other regardless.
+        let opaque_route = FriendsRoute {
+            public_keys: vec![self.state.get_local_public_key().clone(), destination.clone()],
+        };
+        let request_send_funds = RequestSendFunds {
+            request_id: request_id.clone(),
+            route: opaque_route,
+            dest_payment,
+            invoice_id,
+            freeze_links: Vec::new(),
+            opt_blinded_route: Some(blinded_route),
+        };
+        self.queue_to_friend(next_pk, request_send_funds);
+        Some(request_id)
+    }
+
+    /// Read-only snapshot of a friend's current frozen-request count and total frozen credit, for
+    /// AppManager-side reporting. Returns the default (all zero) for a friend with nothing
+    /// outstanding.
+    pub fn friend_frozen_stats(&self, friend_public_key: &PublicKey) -> FriendFrozenStats {
+        self.cache.friend_frozen_stats.get(friend_public_key).cloned().unwrap_or_default()
+    }
+
+    /// Would accepting one more request of `dest_payment` from `friend_public_key` push that
+    /// friend past either per-friend resource limit?
+    fn friend_frozen_limit_exceeded(&self, friend_public_key: &PublicKey, dest_payment: u128) -> bool {
+        let stats = self.friend_frozen_stats(friend_public_key);
+        stats.count >= MAX_FROZEN_REQUESTS_PER_FRIEND
+            || stats.total_credit.saturating_add(dest_payment) > MAX_FROZEN_CREDIT_PER_FRIEND
+    }
+
+    /// Record that a request from `friend_public_key` is now frozen, for per-friend limit
+    /// enforcement. Mirrors `cache.freeze_guard.add_frozen_credit`, but keyed per friend rather
+    /// than aggregated globally.
+    fn record_friend_frozen_credit(&mut self, friend_public_key: &PublicKey, dest_payment: u128) {
+        let stats = self.cache.friend_frozen_stats.entry(friend_public_key.clone()).or_default();
+        stats.count += 1;
+        stats.total_credit = stats.total_credit.saturating_add(dest_payment);
+    }
+
+    /// Release a previously recorded frozen request from `friend_public_key`, dropping the entry
+    /// once nothing is left outstanding from them.
+    fn release_friend_frozen_credit(&mut self, friend_public_key: &PublicKey, dest_payment: u128) {
+        let is_empty = match self.cache.friend_frozen_stats.get_mut(friend_public_key) {
+            Some(stats) => {
+                stats.count = stats.count.saturating_sub(1);
+                stats.total_credit = stats.total_credit.saturating_sub(dest_payment);
+                stats.count == 0
+            },
+            None => return,
+        };
+        if is_empty {
+            self.cache.friend_frozen_stats.remove(friend_public_key);
+        }
+    }
+
+    /// Compute the freeze-link we attach before forwarding from `prev_pk` to `next_pk`: how much
+    /// credit we're willing to vouch for, derived entirely from our own local trust in each of
+    /// them. Shared by `forward_request` and `forward_request_blinded`, since this computation
+    /// only ever depends on our own two immediate neighbors - never on anything further down the
+    /// route, blinded or not.
+    fn build_freeze_link(&self, prev_pk: &PublicKey, next_pk: &PublicKey) -> FunderFreezeLink {
+        let prev_friend = self.state.get_friends().get(prev_pk)
             .expect("Previous friend not present");
-        let next_friend = self.state.get_friends().get(&next_pk)
+        let next_friend = self.state.get_friends().get(next_pk)
             .expect("Next friend not present");
 
-
         let total_trust = self.state.get_total_trust();
         let prev_trust = prev_friend.get_trust();
         let forward_trust = next_friend.get_trust();
@@ -233,31 +1352,87 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
 
         let shared_credits = prev_trust.to_u128().unwrap_or(u128::max_value());
 
-        // Add our freeze link
-        request_send_funds.freeze_links.push(FunderFreezeLink {
+        FunderFreezeLink {
             shared_credits,
             usable_ratio,
-        });
+        }
+    }
+
+    /// Forward a (non-blinded) request message to the relevant friend and token channel.
+    fn forward_request(&mut self, mut request_send_funds: RequestSendFunds) {
+        let index = request_send_funds.route.pk_index(self.state.get_local_public_key())
+            .expect("We are not present in the route!");
+        let prev_index = index.checked_sub(1).expect("We are the originator of this request");
+        let next_index = index.checked_add(1).expect("Index out of range");
+
+        let prev_pk = request_send_funds.route.pk_by_index(prev_index)
+            .expect("Could not obtain previous public key").clone();
+        let next_pk = request_send_funds.route.pk_by_index(next_index)
+            .expect("Could not obtain next public key").clone();
+
+        // Add our freeze link
+        request_send_funds.freeze_links.push(self.build_freeze_link(&prev_pk, &next_pk));
 
         // Queue message to the relevant friend. Later this message will be queued to a specific
         // available token channel:
         let friend_mutation = FriendMutation::PushBackPendingRequest(request_send_funds.clone());
-        let messenger_mutation = FunderMutation::FriendMutation((next_pk.clone(), friend_mutation));
+        let messenger_mutation = FunderMutation::FriendMutation((next_pk, friend_mutation));
+        self.apply_mutation(messenger_mutation);
+    }
+
+    /// `forward_request`'s blinded counterpart: `next_pk` and the still-sealed remainder of the
+    /// onion come from `peel_blinded_hop` rather than from reading `request_send_funds.route`, so
+    /// forwarding a blinded request never requires (or allows) looking past our own immediate
+    /// neighbors.
+    fn forward_request_blinded(&mut self, mut request_send_funds: RequestSendFunds,
+                              prev_pk: PublicKey, next_pk: PublicKey, rest: BlindedRoute) {
+        request_send_funds.freeze_links.push(self.build_freeze_link(&prev_pk, &next_pk));
+        request_send_funds.opt_blinded_route = Some(rest);
+
+        let friend_mutation = FriendMutation::PushBackPendingRequest(request_send_funds.clone());
+        let messenger_mutation = FunderMutation::FriendMutation((next_pk, friend_mutation));
         self.apply_mutation(messenger_mutation);
     }
 
     #[async]
-    fn handle_request_send_funds(mut self, 
+    fn handle_request_send_funds(mut self,
                                remote_public_key: PublicKey,
                                channel_index: u16,
                                request_send_funds: RequestSendFunds) -> Result<Self, HandleFriendError> {
 
+        // Per-friend resource limit, enforced before the (unbounded, global) frozen-credit
+        // bookkeeping below: refuse outright rather than let one friend tie up an unbounded amount
+        // of our frozen-credit capacity.
+        if self.friend_frozen_limit_exceeded(&remote_public_key, request_send_funds.dest_payment) {
+            return await!(self.reply_with_failure(remote_public_key, channel_index, request_send_funds));
+        }
+        self.record_friend_frozen_credit(&remote_public_key, request_send_funds.dest_payment);
+        self.record_request_origin(request_send_funds.request_id.clone(), remote_public_key.clone(), channel_index);
+
         self.cache.freeze_guard.add_frozen_credit(&request_send_funds.create_pending_request());
         // TODO: Add rest of add/sub_frozen_credit
 
+        match request_send_funds.opt_blinded_route.clone() {
+            Some(blinded_route) =>
+                await!(self.forward_or_punt_blinded(remote_public_key, channel_index,
+                                                    request_send_funds, blinded_route)),
+            None =>
+                await!(self.forward_or_punt_plain(remote_public_key, channel_index, request_send_funds)),
+        }
+    }
+
+    /// `handle_request_send_funds`'s non-blinded path, unchanged from before route blinding was
+    /// introduced: destination-vs-forward is read straight off `request_send_funds.route`, which
+    /// every hop along the way can see in full.
+    #[async]
+    fn forward_or_punt_plain(mut self,
+                             remote_public_key: PublicKey,
+                             channel_index: u16,
+                             request_send_funds: RequestSendFunds) -> Result<Self, HandleFriendError> {
+
         // Find ourselves on the route. If we are not there, abort.
         let pk_pair = request_send_funds.route.find_pk_pair(
-            &remote_public_key, 
+            &remote_public_key,
             self.state.get_local_public_key())
             .expect("Could not find pair in request_send_funds route!");
 
@@ -278,7 +1453,7 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
         let next_public_key = request_send_funds.route.pk_by_index(next_index)
             .expect("index out of range!");
         let mut fself = if !self.state.get_friends().contains_key(next_public_key) {
-            await!(self.reply_with_failure(remote_public_key.clone(), 
+            await!(self.reply_with_failure(remote_public_key.clone(),
                                            channel_index,
                                            request_send_funds.clone()))?
         } else {
@@ -295,7 +1470,64 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
             },
             None => {
                 // Queue a failure message to this token channel:
-                await!(fself.reply_with_failure(remote_public_key, 
+                await!(fself.reply_with_failure(remote_public_key,
+                                               channel_index,
+                                               request_send_funds))?
+            },
+        })
+    }
+
+    /// `handle_request_send_funds`'s blinded path: destination-vs-forward is decided by peeling
+    /// `blinded_route` instead of reading `request_send_funds.route`, so this function never
+    /// learns more of the route than our own immediate successor. `initiate_blinded_payment`
+    /// already strips `request_send_funds.route` down to just the originator and destination
+    /// before it ever reaches the wire, so there is nothing sensitive left in that field for this
+    /// path to (not) read.
+    #[async]
+    fn forward_or_punt_blinded(mut self,
+                               remote_public_key: PublicKey,
+                               channel_index: u16,
+                               request_send_funds: RequestSendFunds,
+                               blinded_route: BlindedRoute) -> Result<Self, HandleFriendError> {
+
+        let blinded_hop = match self.peel_blinded_hop(&blinded_route) {
+            Some(blinded_hop) => blinded_hop,
+            None => {
+                // Not addressed to us, or malformed - treat like any other DoS-style rejection.
+                return await!(self.reply_with_failure(remote_public_key, channel_index, request_send_funds));
+            },
+        };
+
+        self.record_hop_secret(request_send_funds.request_id.clone(), blinded_hop.hop_secret);
+
+        let next_public_key = match blinded_hop.opt_next_public_key {
+            None => {
+                // We are the destination; nothing is left to peel.
+                self.punt_request_to_crypter(request_send_funds);
+                return Ok(self);
+            },
+            Some(next_public_key) => next_public_key,
+        };
+
+        let mut fself = if !self.state.get_friends().contains_key(&next_public_key) {
+            await!(self.reply_with_failure(remote_public_key.clone(),
+                                           channel_index,
+                                           request_send_funds.clone()))?
+        } else {
+            self
+        };
+
+        // Perform DoS protection check - unaffected by blinding, since it only ever reads the
+        // freeze_links we and every earlier hop have already appended, never anything further
+        // down the (still-sealed) route:
+        Ok(match fself.cache.freeze_guard.verify_freezing_links(&request_send_funds) {
+            Some(()) => {
+                fself.forward_request_blinded(request_send_funds, remote_public_key,
+                                              next_public_key, blinded_hop.rest);
+                fself
+            },
+            None => {
+                await!(fself.reply_with_failure(remote_public_key,
                                                channel_index,
                                                request_send_funds))?
             },
@@ -308,22 +1540,48 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                                response_send_funds: ResponseSendFunds,
                                pending_request: PendingFriendRequest) {
 
+        if self.cache.expired_requests.remove(&response_send_funds.request_id) {
+            // `timer_tick` already synthesized a local failure for this request and released its
+            // frozen credit; this genuine response arrived too late to matter.
+            return;
+        }
         self.cache.freeze_guard.sub_frozen_credit(&pending_request);
+        // A hop secret recorded for this request_id means we only ever saw it as a blinded
+        // forwarding hop, not as its origin - `pending_request.route` is ours alone in that case,
+        // not the real end-to-end route, so scoring it would train the scorer on noise.
+        if self.take_hop_secret(&response_send_funds.request_id).is_none() {
+            self.cache.route_scorer.record_route_success(&pending_request.route);
+        }
         match self.find_request_origin(&response_send_funds.request_id) {
             None => {
-                // We are the origin of this request, and we got a response.
-                // We should pass it back to crypter.
-                self.messenger_tasks.push(
-                    FunderTask::CrypterFunds(
-                        CrypterFunds::ResponseReceived(ResponseReceived {
-                            request_id: response_send_funds.request_id,
-                            processing_fee_collected: response_send_funds.processing_fee_collected,
-                            response_content: response_send_funds.response_content,
-                        })
-                    )
-                );
+                // We are the origin of this request, and we got a response. If this attempt was
+                // a retry, translate its request_id back to the payment_id the crypter knows
+                // about, and drop the now-settled retry state.
+                let opt_shard_payment_id = self.cache.shard_to_payment.remove(&response_send_funds.request_id);
+                let fallback_payment_id = self.cache.payment_retries.remove(&response_send_funds.request_id)
+                    .map(|retry| retry.payment_id)
+                    .unwrap_or_else(|| response_send_funds.request_id.clone());
+
+                match opt_shard_payment_id {
+                    Some(payment_id) => self.complete_multi_path_shard(payment_id, response_send_funds),
+                    None => {
+                        self.messenger_tasks.push(
+                            FunderTask::CrypterFunds(
+                                CrypterFunds::ResponseReceived(ResponseReceived {
+                                    request_id: fallback_payment_id,
+                                    processing_fee_collected: response_send_funds.processing_fee_collected,
+                                    response_content: response_send_funds.response_content,
+                                })
+                            )
+                        );
+                    },
+                }
             },
             Some((friend_public_key, channel_index)) => {
+                // This response settles the request `friend_public_key` originally sent us -
+                // release their per-friend frozen-credit reservation for it.
+                self.release_friend_frozen_credit(&friend_public_key, pending_request.dest_payment);
+                self.release_request_origin(&response_send_funds.request_id);
                 // Queue this response message to another token channel:
                 let response_op = FriendTcOp::ResponseSendFunds(response_send_funds);
                 let slot_mutation = SlotMutation::PushBackPendingOperation(response_op);
@@ -342,23 +1600,70 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                                pending_request: PendingFriendRequest)
                                 -> Result<Self, HandleFriendError> {
 
+        if self.cache.expired_requests.remove(&failure_send_funds.request_id) {
+            // `timer_tick` already synthesized a local failure for this request and released its
+            // frozen credit; this genuine failure arrived too late to matter.
+            return Ok(self);
+        }
         self.cache.freeze_guard.sub_frozen_credit(&pending_request);
+        // Same reasoning as in handle_response_send_funds: a recorded hop secret means
+        // pending_request.route is only our own local view, not the real route.
+        if self.take_hop_secret(&failure_send_funds.request_id).is_none() {
+            self.cache.route_scorer.record_edge_failure(&pending_request.route, &failure_send_funds.reporting_public_key);
+        }
         let fself = match self.find_request_origin(&failure_send_funds.request_id) {
             None => {
-                // We are the origin of this request, and we got a failure
-                // We should pass it back to crypter.
-                self.messenger_tasks.push(
-                    FunderTask::CrypterFunds(
-                        CrypterFunds::FailureReceived(FailureReceived {
-                            request_id: failure_send_funds.request_id,
-                            reporting_public_key: failure_send_funds.reporting_public_key,
-                        })
-                    )
-                );
-                self
+                // We are the origin of this request, and the attempt we made failed. For a
+                // blinded payment, `reporting_public_key` is meaningless to us (it's whichever
+                // key the reporting hop put there for its own neighbors' sake); recover the real
+                // identity via our own hop secret before acting on it. Non-blinded requests have
+                // no `opt_blinded_report` and fall back to the plaintext field unchanged.
+                let mut fself = self;
+                let resolved_reporting_public_key = failure_send_funds.opt_blinded_report.as_ref()
+                    .and_then(|report| fself.open_failure_report(&failure_send_funds.request_id, report))
+                    .unwrap_or_else(|| failure_send_funds.reporting_public_key.clone());
+
+                // Rather than reporting failure straight to the crypter, retry over an alternate
+                // route that avoids the reporting node - the live retry state (if any) for this
+                // payment is keyed under the request_id of whichever attempt is currently in
+                // flight, which is exactly the one that just failed.
+                let opt_shard_payment_id = fself.cache.shard_to_payment.remove(&failure_send_funds.request_id);
+                let retry = fself.cache.payment_retries.remove(&failure_send_funds.request_id)
+                    .unwrap_or_else(|| PaymentRetry {
+                        payment_id: opt_shard_payment_id.clone().unwrap_or_else(|| failure_send_funds.request_id.clone()),
+                        attempts_used: 1,
+                        blacklist: HashSet::new(),
+                        pending_request: pending_request.clone(),
+                    });
+                match fself.retry_or_give_up(retry, resolved_reporting_public_key, opt_shard_payment_id.clone()) {
+                    RetryOutcome::Retrying => {},
+                    // `reason` only feeds the retry/no-retry decision inside `retry_or_give_up`
+                    // itself; once we've given up, the crypter-facing report looks the same either
+                    // way (this protocol's `FailureReceived` has no slot for a reason code).
+                    RetryOutcome::GaveUp { payment_id, reporting_public_key, reason: _ } => {
+                        match opt_shard_payment_id {
+                            Some(_) => fself.fail_multi_path_payment(payment_id, reporting_public_key),
+                            None => {
+                                fself.messenger_tasks.push(
+                                    FunderTask::CrypterFunds(
+                                        CrypterFunds::FailureReceived(FailureReceived {
+                                            request_id: payment_id,
+                                            reporting_public_key,
+                                        })
+                                    )
+                                );
+                            },
+                        }
+                    },
+                }
+                fself
             },
             Some((friend_public_key, channel_index)) => {
-                let (mut fself, failure_send_funds) = await!(self.failure_message_add_signature(failure_send_funds, 
+                // This failure settles the request `friend_public_key` originally sent us -
+                // release their per-friend frozen-credit reservation for it.
+                self.release_friend_frozen_credit(&friend_public_key, pending_request.dest_payment);
+                self.release_request_origin(&failure_send_funds.request_id);
+                let (mut fself, failure_send_funds) = await!(self.failure_message_add_signature(failure_send_funds,
                                                                pending_request))?;
                 // Queue this failure message to another token channel:
                 let failure_op = FriendTcOp::FailureSendFunds(failure_send_funds);
@@ -404,24 +1709,29 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
     }
 
     /// Handle an error with incoming move token.
-    fn handle_move_token_error(&mut self,
+    /// Compute (without applying) the effects of a failed `simulate_receive_move_token` call:
+    /// notifying AppManager, clearing any stale incoming inconsistency record, and sending our own
+    /// `InconsistencyError` declaring the channel broken. Pure read of the pre-call state - none of
+    /// these mutations are read back by this function - so it's safe to collect them into a
+    /// `DeferredFriendEffects` and commit them together rather than one `apply_mutation`/`add_task`
+    /// call at a time. See `commit_friend_effects`.
+    fn compute_move_token_error_effects(&self,
                                remote_public_key: &PublicKey,
                                channel_index: u16,
-                               receive_move_token_error: ReceiveMoveTokenError) {
+                               receive_move_token_error: ReceiveMoveTokenError) -> DeferredFriendEffects {
+        let mut effects = DeferredFriendEffects::default();
+
         // Send a message about inconsistency problem to AppManager:
-        self.messenger_tasks.push(
+        effects.tasks.push(
             FunderTask::AppManagerFunds(
                 AppManagerFunds::ReceiveMoveTokenError(receive_move_token_error)));
 
-
         // Clear current incoming inconsistency messages:
         let slot_mutation = SlotMutation::SetIncomingInconsistency(IncomingInconsistency::Empty);
         let friend_mutation = FriendMutation::SlotMutation((channel_index, slot_mutation));
-        let messenger_mutation = FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-        self.apply_mutation(messenger_mutation);
-
+        effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
 
-        let token_channel_slot = self.get_token_channel_slot(&remote_public_key, 
+        let token_channel_slot = self.get_token_channel_slot(&remote_public_key,
                                                               channel_index);
         // Send an InconsistencyError message to remote side:
         let current_token = token_channel_slot.directional
@@ -436,15 +1746,16 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
             balance_for_reset,
         };
 
-        self.messenger_tasks.push(
+        effects.tasks.push(
             FunderTask::FriendFunds(
                 FriendFunds::InconsistencyError(inconsistency_error)));
 
         // Keep outgoing InconsistencyError message details in memory:
         let slot_mutation = SlotMutation::SetOutgoingInconsistency(OutgoingInconsistency::Sent);
         let friend_mutation = FriendMutation::SlotMutation((channel_index, slot_mutation));
-        let messenger_mutation = FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-        self.apply_mutation(messenger_mutation);
+        effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
+
+        effects
     }
 
 
@@ -637,7 +1948,19 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
             },
             ReceiveMoveTokenOutput::Received(move_token_received) => {
 
-                let MoveTokenReceived {incoming_messages, mutations} = 
+                // This is a genuine, non-duplicate move token: the friend has made progress,
+                // so its retransmit backoff and missed-tick count reset here rather than waiting
+                // for the next `timer_tick`.
+                if let Some(liveness_update) = self.record_friend_progress(&remote_public_key) {
+                    self.messenger_tasks.push(
+                        FunderTask::AppManagerFunds(
+                            AppManagerFunds::FriendLiveness(liveness_update)));
+                }
+                // A move-token exchange just completed on this slot, so it's no longer
+                // "unestablished" regardless of its balance - see `admit_new_slot`.
+                self.mark_slot_established(&remote_public_key, channel_index);
+
+                let MoveTokenReceived {incoming_messages, mutations} =
                     move_token_received;
 
                 // Apply all mutations:
@@ -689,7 +2012,24 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
             return Ok(self)
         }
 
-        let token_channel_slot = self.get_token_channel_slot(&remote_public_key, 
+        if !self.admit_new_slot(&remote_public_key, channel_index) {
+            // We've hit the unestablished-slot budget for this (untrusted) friend, or globally.
+            // Reuse the same FriendSetMaxTokenChannels mechanism as the over-index case above,
+            // advertising channel_index itself as the new ceiling so the remote knows this
+            // specific index (and anything past it) isn't available right now.
+            self.messenger_tasks.push(
+                FunderTask::FriendFunds(
+                    FriendFunds::SetMaxTokenChannels(
+                        FriendSetMaxTokenChannels {
+                            max_token_channels: channel_index,
+                        }
+                    )
+                )
+            );
+            return Ok(self)
+        }
+
+        let token_channel_slot = self.get_token_channel_slot(&remote_public_key,
                                                              channel_index);
 
         // Check if the channel is inconsistent.
@@ -733,18 +2073,33 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                                              is_empty))?
             },
             Err(receive_move_token_error) => {
-                fself.handle_move_token_error(&remote_public_key,
+                let effects = fself.compute_move_token_error_effects(&remote_public_key,
                                              channel_index,
                                              receive_move_token_error);
+                fself.commit_friend_effects(effects);
                 fself
             },
         })
     }
 
-    fn handle_inconsistency_error(&mut self, 
+    /// Compute (without applying) the effects of an incoming `FriendInconsistencyError`: recording
+    /// the remote's reset terms, and deciding whether we still owe it our own (unsent, or sent but
+    /// not yet acked). Nothing here is read back later in the same call, so - unlike
+    /// `handle_move_token`, which needs `check_reset_channel`'s reset applied immediately to keep
+    /// processing the same incoming move token against the right state - this can be computed in
+    /// full before anything is committed. See `DeferredFriendEffects` / `commit_friend_effects`.
+    /// Besides the effects, reports the `token_channel_index` of a channel whose reset was just
+    /// finalized (see the `OutgoingInconsistency::Sent` arm below), so the caller can drive the
+    /// empty outgoing move token that restarts the flow - that has to happen after the
+    /// `SlotMutation::ResetChannel` mutation in this bundle is actually applied, since it reads
+    /// back the freshly reconstructed directional state, so it can't be folded into the bundle
+    /// itself the way the other mutations/tasks are.
+    fn compute_inconsistency_error_effects(&self,
                                   remote_public_key: &PublicKey,
-                                  friend_inconsistency_error: FriendInconsistencyError) {
-        
+                                  friend_inconsistency_error: FriendInconsistencyError) -> (DeferredFriendEffects, Option<u16>) {
+        let mut effects = DeferredFriendEffects::default();
+        let mut completed_reset = None;
+
         // Save incoming inconsistency details:
         let token_channel_index = friend_inconsistency_error.token_channel_index;
         let incoming = IncomingInconsistency::Incoming(ResetTerms {
@@ -753,8 +2108,7 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
         });
 
         let friend_mutation = FriendMutation::SetIncomingInconsistency(incoming);
-        let messenger_mutation = FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-        self.apply_mutation(messenger_mutation);
+        effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
 
         // Obtain information about our reset terms:
         let tc_slot = self.get_token_channel_slot(
@@ -768,8 +2122,7 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
         let should_send_outgoing = match tc_slot.inconsistency_status.outgoing {
             OutgoingInconsistency::Empty => {
                 let friend_mutation = FriendMutation::SetOutgoingInconsistency(OutgoingInconsistency::Sent);
-                let messenger_mutation = FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-                self.apply_mutation(messenger_mutation);
+                effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
                 true
             },
             OutgoingInconsistency::Sent => {
@@ -779,13 +2132,48 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                 };
                 if is_ack_valid {
                     let friend_mutation = FriendMutation::SetOutgoingInconsistency(OutgoingInconsistency::Acked);
-                    let messenger_mutation = FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
-                    self.apply_mutation(messenger_mutation);
-                    false
+                    effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
+
+                    // Both sides have now converged: we just saved the peer's freshly-advertised
+                    // reset terms above, and our own outgoing terms were just acked. Rather than
+                    // wait for a move token bearing the reset token - which may never come if the
+                    // channel is wedged - finish the reset right here. Both ends pick between the
+                    // same two candidate tokens (ours and the peer's) with the same comparison, so
+                    // they deterministically agree on the resulting token and, with it, on which
+                    // side holds it first.
+                    let peer_token = friend_inconsistency_error.current_token.clone();
+                    let agreed_token = if reset_token > peer_token {
+                        reset_token.clone()
+                    } else {
+                        peer_token
+                    };
+                    let slot_mutation = SlotMutation::ResetChannel(ResetTerms {
+                        current_token: agreed_token,
+                        balance_for_reset,
+                    });
+                    let friend_mutation = FriendMutation::SlotMutation((token_channel_index, slot_mutation));
+                    effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
+
+                    let friend_mutation = FriendMutation::SetIncomingInconsistency(IncomingInconsistency::Empty);
+                    effects.mutations.push(FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation)));
+
+                    // Deliberately *not* setting OutgoingInconsistency back to Empty here: we stay
+                    // at Acked so that if the peer replies with its own terminal ack below (it
+                    // doesn't know yet that we've completed unless we tell it - see below), the
+                    // `OutgoingInconsistency::Acked => false` arm absorbs that reply without
+                    // restarting the Empty->Sent cycle and bouncing forever.
+                    completed_reset = Some(token_channel_index);
+
+                    // The peer has no way to know we've finished unless we tell it: its own
+                    // outgoing status is still stuck at `Sent` (or `Empty`, if it hasn't even
+                    // noticed the break yet) until it sees an ack for *its* current_token. Without
+                    // this, whichever side reaches `Acked` first would finish alone while the other
+                    // waited forever for an ack that never comes.
+                    true
                 } else {
                     true
                 }
-                
+
             },
             OutgoingInconsistency::Acked => false,
         };
@@ -799,23 +2187,30 @@ impl<A: Clone, R: SecureRandom + 'static> MutableFunderHandler<A,R> {
                 balance_for_reset,
             };
 
-            self.add_task(
+            effects.tasks.push(
                 FunderTask::FriendFunds(
                     FriendFunds::InconsistencyError(inconsistency_error)));
         }
 
+        (effects, completed_reset)
     }
 
     #[async]
-    pub fn handle_friend_message(mut self, 
-                                   remote_public_key: PublicKey, 
+    pub fn handle_friend_message(mut self,
+                                   remote_public_key: PublicKey,
                                    friend_message: IncomingFriendFunds)
                                         -> Result<Self, HandleFriendError> {
         match friend_message {
             IncomingFriendFunds::MoveToken(friend_move_token) =>
                 await!(self.handle_move_token(remote_public_key, friend_move_token)),
             IncomingFriendFunds::InconsistencyError(friend_inconsistency_error) => {
-                self.handle_inconsistency_error(&remote_public_key, friend_inconsistency_error);
+                let (effects, completed_reset) = self.compute_inconsistency_error_effects(&remote_public_key, friend_inconsistency_error);
+                self.commit_friend_effects(effects);
+                if let Some(token_channel_index) = completed_reset {
+                    // Kick off a fresh, empty move token on the just-reconstructed channel so the
+                    // two sides actually resume exchanging moves rather than sitting idle.
+                    self.send_through_token_channel(&remote_public_key, token_channel_index, false);
+                }
                 Ok(self)
             }
         }
@@ -13,10 +13,6 @@
 //! which is designed for working with timers, timeouts, and intervals with the
 //! [`futures`][futures] crate.
 //!
-//! ## Unsolved problem
-//!
-//! - Graceful shutdown
-//!
 //! [futures]: https://github.com/alexcrichton/futures
 //! [futures-timer]: https://github.com/alexcrichton/futures-timer
 
@@ -25,6 +21,7 @@
 use std::{time::Duration};
 use futures::prelude::*;
 use futures::prelude::{async, await};
+use futures::{stream, Async};
 use futures::sync::{mpsc, oneshot};
 
 use tokio_core::reactor::{Handle, Interval};
@@ -35,7 +32,6 @@ pub struct TimerTick;
 pub enum TimerError {
     IntervalCreationError,
     IncomingError,
-    IncomingRequestsClosed,
     IncomingRequestsError,
 }
 
@@ -45,8 +41,36 @@ pub enum TimerClientError {
     ResponseCanceled,
 }
 
-struct TimerRequest {
-    response_sender: oneshot::Sender<mpsc::Receiver<TimerTick>>,
+enum TimerRequest {
+    CreateStream {
+        response_sender: oneshot::Sender<mpsc::Receiver<TimerTick>>,
+    },
+    /// Like `CreateStream`, but the returned receiver closes on its own after `ticks` ticks,
+    /// sparing the caller from counting ticks itself to implement a deadline.
+    OneShotStream {
+        ticks: u64,
+        response_sender: oneshot::Sender<mpsc::Receiver<TimerTick>>,
+    },
+    /// Like `CreateStream`, but the response also carries a cancel token: dropping it or sending
+    /// on it removes the stream from the broadcast, closing the receiver early.
+    CancellableStream {
+        response_sender: oneshot::Sender<(mpsc::Receiver<TimerTick>, oneshot::Sender<()>)>,
+    },
+    NumClients {
+        response_sender: oneshot::Sender<usize>,
+    },
+    Shutdown,
+}
+
+/// Per-subscriber bookkeeping kept alongside each broadcast sender.
+struct TickSenderEntry {
+    sender: mpsc::Sender<TimerTick>,
+    /// `Some(n)` for a one-shot stream whose single tick is sent `n` ticks from now; `None` for an
+    /// unbounded broadcast stream.
+    remaining_ticks: Option<u64>,
+    /// `Some` for a cancellable stream; polled on every tick to check whether the caller asked
+    /// for early cancellation.
+    cancel_receiver: Option<oneshot::Receiver<()>>,
 }
 
 #[derive(Clone)]
@@ -64,7 +88,7 @@ impl TimerClient {
     #[async]
     pub fn request_timer_stream(self) -> Result<mpsc::Receiver<TimerTick>, TimerClientError> {
         let (response_sender, response_receiver) = oneshot::channel();
-        let timer_request = TimerRequest { response_sender };
+        let timer_request = TimerRequest::CreateStream { response_sender };
         let _ = match await!(self.sender.send(timer_request)) {
             Ok(sender) => Ok(sender),
             Err(_) => Err(TimerClientError::SendFailure),
@@ -74,48 +98,171 @@ impl TimerClient {
             Err(_) => Err(TimerClientError::ResponseCanceled),
         }
     }
+
+    /// Request a stream that emits a single `TimerTick` after `ticks` ticks and then closes,
+    /// without the caller having to count ticks itself to implement a timeout or deadline.
+    #[async]
+    pub fn request_one_shot(self, ticks: u64) -> Result<mpsc::Receiver<TimerTick>, TimerClientError> {
+        let (response_sender, response_receiver) = oneshot::channel();
+        let timer_request = TimerRequest::OneShotStream { ticks, response_sender };
+        let _ = match await!(self.sender.send(timer_request)) {
+            Ok(sender) => Ok(sender),
+            Err(_) => Err(TimerClientError::SendFailure),
+        }?;
+        match await!(response_receiver) {
+            Ok(timer_stream) => Ok(timer_stream),
+            Err(_) => Err(TimerClientError::ResponseCanceled),
+        }
+    }
+
+    /// Request a broadcast stream that can be cancelled early: dropping, or sending on, the
+    /// returned `oneshot::Sender` removes the stream from the broadcast and closes it, without
+    /// waiting for the whole `TimerClient` to shut down.
+    #[async]
+    pub fn request_cancellable_stream(self) -> Result<(mpsc::Receiver<TimerTick>, oneshot::Sender<()>), TimerClientError> {
+        let (response_sender, response_receiver) = oneshot::channel();
+        let timer_request = TimerRequest::CancellableStream { response_sender };
+        let _ = match await!(self.sender.send(timer_request)) {
+            Ok(sender) => Ok(sender),
+            Err(_) => Err(TimerClientError::SendFailure),
+        }?;
+        match await!(response_receiver) {
+            Ok(timer_stream_and_cancel) => Ok(timer_stream_and_cancel),
+            Err(_) => Err(TimerClientError::ResponseCanceled),
+        }
+    }
+
+    /// Query the number of live timer streams currently being broadcast to.
+    /// Mostly useful for tests and for detecting streams that were requested but never dropped.
+    #[async]
+    pub fn get_num_clients(self) -> Result<usize, TimerClientError> {
+        let (response_sender, response_receiver) = oneshot::channel();
+        let timer_request = TimerRequest::NumClients { response_sender };
+        let _ = match await!(self.sender.send(timer_request)) {
+            Ok(sender) => Ok(sender),
+            Err(_) => Err(TimerClientError::SendFailure),
+        }?;
+        match await!(response_receiver) {
+            Ok(num_clients) => Ok(num_clients),
+            Err(_) => Err(TimerClientError::ResponseCanceled),
+        }
+    }
+
+    /// Ask the timer loop to drop every outstanding `TimerTick` sender and return. Once this
+    /// resolves, all `mpsc::Receiver<TimerTick>` streams previously handed out observe
+    /// end-of-stream rather than hanging forever.
+    #[async]
+    pub fn shutdown(self) -> Result<(), TimerClientError> {
+        match await!(self.sender.send(TimerRequest::Shutdown)) {
+            Ok(_sender) => Ok(()),
+            Err(_) => Err(TimerClientError::SendFailure),
+        }
+    }
 }
 
 enum TimerEvent {
     Incoming,
+    IncomingClosed,
     Request(TimerRequest),
+    FromClientClosed,
 }
 
 #[async]
-fn timer_loop<M: 'static>(incoming: M, from_client: mpsc::Receiver<TimerRequest>) -> Result<!, TimerError> 
+fn timer_loop<M: 'static>(incoming: M, from_client: mpsc::Receiver<TimerRequest>) -> Result<(), TimerError>
 where
     M: Stream<Item=(), Error=()>,
 {
+    // Closure of either side is an orderly shutdown, not an error: once nobody can possibly send
+    // us another tick or request, there is nothing left to broadcast. A sentinel event marks each
+    // side's end so the loop below can tell "no more events, ever" apart from "no event yet".
     let incoming = incoming.map(|_| TimerEvent::Incoming)
-        .map_err(|_| TimerError::IncomingError);
-    let from_client = from_client.map(|timer_request| TimerEvent::Request(timer_request))
-        .map_err(|_| TimerError::IncomingRequestsError);
+        .map_err(|_| TimerError::IncomingError)
+        .chain(stream::once(Ok(TimerEvent::IncomingClosed)));
+    let from_client = from_client.map(TimerEvent::Request)
+        .map_err(|_| TimerError::IncomingRequestsError)
+        .chain(stream::once(Ok(TimerEvent::FromClientClosed)));
 
-    // TODO: What happens if one of the two streams (incoming, from_client) is closed?
     let events = incoming.select(from_client);
-    let mut tick_senders: Vec<mpsc::Sender<TimerTick>> = Vec::new();
+    let mut tick_senders: Vec<TickSenderEntry> = Vec::new();
 
     #[async]
     for event in events {
         match event {
             TimerEvent::Incoming => {
-                let mut temp_tick_senders = Vec::new();
-                temp_tick_senders.append(&mut tick_senders);
-                for tick_sender in temp_tick_senders {
-                    if let Ok(tick_sender) = await!(tick_sender.send(TimerTick)) {
-                        tick_senders.push(tick_sender);
+                let mut temp_entries = Vec::new();
+                temp_entries.append(&mut tick_senders);
+                for entry in temp_entries {
+                    let TickSenderEntry { sender, remaining_ticks, mut cancel_receiver } = entry;
+
+                    let cancelled = match cancel_receiver.as_mut() {
+                        Some(cancel_receiver) => match cancel_receiver.poll() {
+                            Ok(Async::NotReady) => false,
+                            _ => true,
+                        },
+                        None => false,
+                    };
+                    if cancelled || remaining_ticks == Some(0) {
+                        continue;
+                    }
+                    // A one-shot stream stays silent until its last tick.
+                    if let Some(n) = remaining_ticks {
+                        if n > 1 {
+                            tick_senders.push(TickSenderEntry { sender, remaining_ticks: Some(n - 1), cancel_receiver });
+                            continue;
+                        }
+                    }
+
+                    if let Ok(sender) = await!(sender.send(TimerTick)) {
+                        let remaining_ticks = remaining_ticks.map(|n| n - 1);
+                        if remaining_ticks != Some(0) {
+                            tick_senders.push(TickSenderEntry { sender, remaining_ticks, cancel_receiver });
+                        }
                     }
                 }
             },
-            TimerEvent::Request(timer_request) => {
+            TimerEvent::Request(TimerRequest::CreateStream { response_sender }) => {
                 let (tick_sender, tick_receiver) = mpsc::channel(0);
-                tick_senders.push(tick_sender);
-                let _ = timer_request.response_sender.send(tick_receiver);
+                tick_senders.push(TickSenderEntry {
+                    sender: tick_sender,
+                    remaining_ticks: None,
+                    cancel_receiver: None,
+                });
+                let _ = response_sender.send(tick_receiver);
+            },
+            TimerEvent::Request(TimerRequest::OneShotStream { ticks, response_sender }) => {
+                let (tick_sender, tick_receiver) = mpsc::channel(0);
+                if ticks > 0 {
+                    tick_senders.push(TickSenderEntry {
+                        sender: tick_sender,
+                        remaining_ticks: Some(ticks),
+                        cancel_receiver: None,
+                    });
+                }
+                let _ = response_sender.send(tick_receiver);
+            },
+            TimerEvent::Request(TimerRequest::CancellableStream { response_sender }) => {
+                let (tick_sender, tick_receiver) = mpsc::channel(0);
+                let (cancel_sender, cancel_receiver) = oneshot::channel();
+                tick_senders.push(TickSenderEntry {
+                    sender: tick_sender,
+                    remaining_ticks: None,
+                    cancel_receiver: Some(cancel_receiver),
+                });
+                let _ = response_sender.send((tick_receiver, cancel_sender));
+            },
+            TimerEvent::Request(TimerRequest::NumClients { response_sender }) => {
+                let _ = response_sender.send(tick_senders.len());
+            },
+            TimerEvent::Request(TimerRequest::Shutdown) | TimerEvent::IncomingClosed | TimerEvent::FromClientClosed => {
+                // Dropping every sender closes each corresponding `mpsc::Receiver<TimerTick>`,
+                // so consumers observe end-of-stream instead of hanging.
+                tick_senders.clear();
+                return Ok(());
             },
         }
     }
 
-    Err(TimerError::IncomingRequestsClosed)
+    Ok(())
 }
 
 /// Create a timer service that broadcasts everything from the incoming Stream.